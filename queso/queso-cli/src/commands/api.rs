@@ -0,0 +1,94 @@
+use crate::{typescript, utils::ProjectPaths};
+use anyhow::{Result, bail};
+use clap::{Args, Subcommand};
+use console::style;
+use std::path::{Path, PathBuf};
+
+/// Operations under this prefix get a generated client function.
+const PREFIX: &str = "/api/v1";
+
+#[derive(Debug, Args)]
+pub struct ApiCommand {
+    #[command(subcommand)]
+    command: ApiSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum ApiSubcommand {
+    /// Write the OpenAPI document and the TypeScript client into queso-ui
+    Export,
+    /// Fail if the committed OpenAPI document or TypeScript client is stale
+    Check,
+}
+
+impl ApiCommand {
+    pub async fn run(&self, paths: ProjectPaths) -> Result<()> {
+        match &self.command {
+            ApiSubcommand::Export => export(&paths.ui),
+            ApiSubcommand::Check => check(&paths.ui),
+        }
+    }
+}
+
+/// Each generated file under the UI directory `ui` and its expected
+/// contents.
+pub fn outputs(ui: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let spec = queso_server::openapi::v1();
+    let json = serde_json::to_value(&spec)?;
+    let dir = ui.join("src").join("api");
+
+    Ok(vec![
+        (
+            dir.join("openapi.gen.json"),
+            format!("{}\n", serde_json::to_string_pretty(&json)?),
+        ),
+        (dir.join("api.gen.ts"), typescript::generate(&json, PREFIX)?),
+    ])
+}
+
+/// Generated files whose committed contents differ from `outputs`.
+pub fn stale(ui: &Path) -> Result<Vec<PathBuf>> {
+    Ok(outputs(ui)?
+        .into_iter()
+        .filter(|(path, contents)| std::fs::read_to_string(path).ok().as_ref() != Some(contents))
+        .map(|(path, _)| path)
+        .collect())
+}
+
+fn export(ui: &Path) -> Result<()> {
+    for (path, contents) in outputs(ui)? {
+        std::fs::write(&path, contents)?;
+        println!("{} Wrote {}", style("✓").green(), path.display());
+    }
+    Ok(())
+}
+
+fn check(ui: &Path) -> Result<()> {
+    let stale = stale(ui)?;
+    if stale.is_empty() {
+        println!("{} Generated API files are up to date", style("✓").green());
+        return Ok(());
+    }
+
+    for path in &stale {
+        println!("{} {} is out of date", style("✗").red(), path.display());
+    }
+    bail!("Generated API files are stale; run `queso api export` and commit the result")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_committed_output_is_current() {
+        let ui = Path::new(env!("CARGO_MANIFEST_DIR")).join("../queso-ui");
+
+        let stale = stale(&ui).unwrap();
+        assert!(
+            stale.is_empty(),
+            "{:?} are stale; run `queso api export`",
+            stale
+        );
+    }
+}
@@ -244,21 +244,21 @@ impl<T: CommandExecutor> EnvironmentManager<T> {
             style("Starting backend server...").cyan()
         );
 
-        if !self.check_port_available(port) {
-            if let Some(pid) = self.get_process_on_port(port) {
-                println!(
-                    "{} Port {} is in use by process {}. Kill it? [y/N]",
-                    style("!").yellow(),
-                    port,
-                    pid
-                );
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input)?;
-                if input.trim().to_lowercase() == "y" {
-                    self.kill_process(&pid)?;
-                } else {
-                    anyhow::bail!("Port {} is already in use", port);
-                }
+        if !self.check_port_available(port)
+            && let Some(pid) = self.get_process_on_port(port)
+        {
+            println!(
+                "{} Port {} is in use by process {}. Kill it? [y/N]",
+                style("!").yellow(),
+                port,
+                pid
+            );
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            if input.trim().to_lowercase() == "y" {
+                self.kill_process(&pid)?;
+            } else {
+                anyhow::bail!("Port {} is already in use", port);
             }
         }
 
@@ -283,21 +283,21 @@ impl<T: CommandExecutor> EnvironmentManager<T> {
             style("Starting frontend server...").cyan()
         );
 
-        if !self.check_port_available(port) {
-            if let Some(pid) = self.get_process_on_port(port) {
-                println!(
-                    "{} Port {} is in use by process {}. Kill it? [y/N]",
-                    style("!").yellow(),
-                    port,
-                    pid
-                );
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input)?;
-                if input.trim().to_lowercase() == "y" {
-                    self.kill_process(&pid)?;
-                } else {
-                    anyhow::bail!("Port {} is already in use", port);
-                }
+        if !self.check_port_available(port)
+            && let Some(pid) = self.get_process_on_port(port)
+        {
+            println!(
+                "{} Port {} is in use by process {}. Kill it? [y/N]",
+                style("!").yellow(),
+                port,
+                pid
+            );
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            if input.trim().to_lowercase() == "y" {
+                self.kill_process(&pid)?;
+            } else {
+                anyhow::bail!("Port {} is already in use", port);
             }
         }
 
@@ -1,3 +1,5 @@
+pub mod api;
 pub mod dev;
 
+pub use api::ApiCommand;
 pub use dev::DevCommand;
@@ -1,9 +1,10 @@
 mod commands;
+mod typescript;
 mod utils;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use commands::DevCommand;
+use commands::{ApiCommand, DevCommand};
 use utils::{find_git_root, get_project_paths};
 
 #[derive(Debug, Parser)]
@@ -18,6 +19,8 @@ struct Cli {
 enum Commands {
     /// Manage development environment
     Dev(DevCommand),
+    /// Export the OpenAPI document and generated TypeScript client
+    Api(ApiCommand),
 }
 
 #[tokio::main]
@@ -35,5 +38,6 @@ async fn main() -> Result<()> {
     // Execute command
     match cli.command {
         Commands::Dev(cmd) => cmd.run(paths).await,
+        Commands::Api(cmd) => cmd.run(paths).await,
     }
 }
@@ -0,0 +1,455 @@
+//! TypeScript types and an axios client generated from the server's OpenAPI
+//! document.
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+const HEADER: &str = "\
+// Generated by `queso api export` from the server's OpenAPI document.
+// Do not edit; change the server and run the command again.
+";
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+/// A module with an interface or alias per component schema, plus a
+/// `createApi` factory with one function per operation under `prefix`. The
+/// axios instance passed to it must use `prefix` as its base URL.
+pub fn generate(spec: &Value, prefix: &str) -> Result<String> {
+    let mut out = String::from(HEADER);
+    out.push_str("\nimport type { AxiosInstance } from 'axios';\n");
+
+    let schemas = spec
+        .pointer("/components/schemas")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    for (name, schema) in &schemas {
+        out.push('\n');
+        out.push_str(&doc_comment(schema, ""));
+        out.push_str(&declaration(name, schema)?);
+    }
+
+    out.push_str(&format!(
+        "\n/** Every `{}` operation; `http` must use `{}` as its base URL. */\n",
+        prefix, prefix
+    ));
+    out.push_str("export const createApi = (http: AxiosInstance) => ({\n");
+    let paths = spec
+        .get("paths")
+        .and_then(Value::as_object)
+        .context("The document has no paths")?;
+    for (path, item) in paths {
+        let Some(path) = path.strip_prefix(prefix) else {
+            continue;
+        };
+        for method in METHODS {
+            if let Some(operation) = item.get(method) {
+                out.push_str(&operation_fn(method, path, operation)?);
+            }
+        }
+    }
+    out.push_str("});\n");
+
+    Ok(out)
+}
+
+fn declaration(name: &str, schema: &Value) -> Result<String> {
+    if schema.get("type").and_then(Value::as_str) == Some("object") {
+        let mut out = format!("export interface {} {{\n", name);
+        out.push_str(&properties(schema, "  ")?);
+        out.push_str("}\n");
+        Ok(out)
+    } else {
+        Ok(format!("export type {} = {};\n", name, type_of(schema)?))
+    }
+}
+
+/// Interface members; write-only properties only appear in requests and
+/// are left out.
+fn properties(schema: &Value, indent: &str) -> Result<String> {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let empty = Map::new();
+    let properties = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    let mut out = String::new();
+    for (name, property) in properties {
+        if property.get("writeOnly") == Some(&Value::Bool(true)) {
+            continue;
+        }
+        let optional = if required.contains(&name.as_str()) {
+            ""
+        } else {
+            "?"
+        };
+        out.push_str(&doc_comment(property, indent));
+        writeln!(
+            out,
+            "{}{}{}: {};",
+            indent,
+            property_name(name),
+            optional,
+            type_of(property)?
+        )?;
+    }
+    Ok(out)
+}
+
+fn type_of(schema: &Value) -> Result<String> {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference
+            .strip_prefix("#/components/schemas/")
+            .map(str::to_string)
+            .with_context(|| format!("Unsupported reference {}", reference));
+    }
+    for (key, separator) in [("oneOf", " | "), ("anyOf", " | "), ("allOf", " & ")] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            let types = variants.iter().map(type_of).collect::<Result<Vec<_>>>()?;
+            return Ok(types.join(separator));
+        }
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return Ok(values
+            .iter()
+            .map(Value::to_string)
+            .map(|value| value.replace('"', "'"))
+            .collect::<Vec<_>>()
+            .join(" | "));
+    }
+
+    // `type` is a string, or a list like `["string", "null"]`
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => return Ok("unknown".to_string()),
+    };
+    let types = types
+        .into_iter()
+        .map(|name| primitive(name, schema))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(types.join(" | "))
+}
+
+fn primitive(name: &str, schema: &Value) -> Result<String> {
+    Ok(match name {
+        "string" => "string".to_string(),
+        "integer" | "number" => "number".to_string(),
+        "boolean" => "boolean".to_string(),
+        "null" => "null".to_string(),
+        "array" => {
+            let items = schema.get("items").map(type_of).transpose()?;
+            let items = items.unwrap_or_else(|| "unknown".to_string());
+            if items.contains(' ') {
+                format!("({})[]", items)
+            } else {
+                format!("{}[]", items)
+            }
+        }
+        "object" => match schema.get("additionalProperties") {
+            Some(values @ Value::Object(_)) => format!("Record<string, {}>", type_of(values)?),
+            _ if schema.get("properties").is_some() => {
+                format!("{{\n{}}}", properties(schema, "  ")?)
+            }
+            _ => "Record<string, unknown>".to_string(),
+        },
+        other => bail!("Unsupported schema type {}", other),
+    })
+}
+
+fn operation_fn(method: &str, path: &str, operation: &Value) -> Result<String> {
+    let id = operation
+        .get("operationId")
+        .and_then(Value::as_str)
+        .with_context(|| format!("{} {} has no operationId", method, path))?;
+
+    let mut params = Vec::new();
+    // (header name, argument, required)
+    let mut headers = Vec::new();
+    for parameter in operation
+        .get("parameters")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let name = parameter.get("name").and_then(Value::as_str).unwrap_or("");
+        let schema = parameter.get("schema").unwrap_or(&Value::Null);
+        match parameter.get("in").and_then(Value::as_str) {
+            Some("path") => params.push(format!("{}: {}", camel_case(name), type_of(schema)?)),
+            Some("header") => {
+                let required = parameter.get("required") == Some(&Value::Bool(true));
+                let argument = camel_case(&name.to_ascii_lowercase());
+                headers.push((name, argument, required, type_of(schema)?));
+            }
+            _ => bail!(
+                "{}: only path and header parameters are supported, not {}",
+                id,
+                name
+            ),
+        }
+    }
+    let body = operation
+        .pointer("/requestBody/content/application~1json/schema")
+        .map(type_of)
+        .transpose()?;
+    if let Some(body) = &body {
+        params.push(format!("body: {}", body));
+    }
+    // Optional arguments go last
+    headers.sort_by_key(|(_, _, required, _)| !required);
+    for (_, argument, required, ts) in &headers {
+        let optional = if *required { "" } else { "?" };
+        params.push(format!("{}{}: {}", argument, optional, ts));
+    }
+
+    let mut responses = Vec::new();
+    for (status, response) in operation
+        .get("responses")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+    {
+        if !status.starts_with('2') {
+            continue;
+        }
+        if let Some(schema) = response.pointer("/content/application~1json/schema") {
+            let response = type_of(schema)?;
+            if !responses.contains(&response) {
+                responses.push(response);
+            }
+        }
+    }
+
+    let url = if path.contains('{') {
+        format!("`{}`", path.replace('{', "${"))
+    } else {
+        format!("'{}'", path)
+    };
+    let url = camel_case_params(&url);
+    let mut args = match &body {
+        Some(_) => format!("{}, body", url),
+        None if headers.is_empty() || matches!(method, "get" | "delete") => url,
+        None => format!("{}, undefined", url),
+    };
+    if !headers.is_empty() {
+        let fields: Vec<String> = headers
+            .iter()
+            .map(|(name, argument, _, _)| format!("'{}': {}", name, argument))
+            .collect();
+        write!(args, ", {{ headers: {{ {} }} }}", fields.join(", "))?;
+    }
+
+    let mut out = doc_comment(operation, "  ");
+    let signature = format!("  {}: async ({})", camel_case(id), params.join(", "));
+    if responses.is_empty() {
+        writeln!(out, "{}: Promise<void> => {{", signature)?;
+        writeln!(out, "    await http.{}({});", method, args)?;
+        out.push_str("  },\n");
+    } else {
+        let response = responses.join(" | ");
+        writeln!(out, "{}: Promise<{}> =>", signature, response)?;
+        writeln!(
+            out,
+            "    (await http.{}<{}>({})).data,",
+            method, response, args
+        )?;
+    }
+    Ok(out)
+}
+
+/// `/users/${user_id}` with the parameter names in camel case.
+fn camel_case_params(url: &str) -> String {
+    let mut out = String::new();
+    let mut rest = url;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end);
+        out.push_str(&rest[..start + 2]);
+        out.push_str(&camel_case(&rest[start + 2..end]));
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' || c == '-' {
+            upper = !out.is_empty();
+        } else if upper {
+            out.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn property_name(name: &str) -> String {
+    let identifier = name.chars().enumerate().all(|(i, c)| {
+        c == '_' || c == '$' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+    });
+    if identifier {
+        name.to_string()
+    } else {
+        format!("'{}'", name)
+    }
+}
+
+/// The schema's `description`, or an operation's `summary`, as JSDoc.
+fn doc_comment(schema: &Value, indent: &str) -> String {
+    let Some(text) = schema
+        .get("summary")
+        .or_else(|| schema.get("description"))
+        .and_then(Value::as_str)
+    else {
+        return String::new();
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    if let [line] = lines.as_slice() {
+        return format!("{}/** {} */\n", indent, line);
+    }
+
+    let mut out = format!("{}/**\n", indent);
+    for line in lines {
+        out.push_str(format!("{} * {}", indent, line).trim_end());
+        out.push('\n');
+    }
+    out.push_str(&format!("{} */\n", indent));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec() -> Value {
+        json!({
+            "paths": {
+                "/health/live": {"get": {"operationId": "live", "responses": {}}},
+                "/api/v1/users/{user_id}": {
+                    "get": {
+                        "operationId": "get_user",
+                        "summary": "Get a user by ID",
+                        "parameters": [
+                            {"name": "user_id", "in": "path", "schema": {"type": "integer"}}
+                        ],
+                        "responses": {
+                            "200": {"content": {"application/json": {
+                                "schema": {"$ref": "#/components/schemas/User"}
+                            }}},
+                            "404": {"content": {"application/problem+json": {
+                                "schema": {"$ref": "#/components/schemas/Problem"}
+                            }}}
+                        }
+                    },
+                    "delete": {
+                        "operationId": "delete_user",
+                        "parameters": [
+                            {"name": "user_id", "in": "path", "schema": {"type": "integer"}},
+                            {"name": "X-Reason", "in": "header", "schema": {"type": "string"}},
+                            {
+                                "name": "If-Match",
+                                "in": "header",
+                                "required": true,
+                                "schema": {"type": "string"}
+                            }
+                        ],
+                        "responses": {"204": {"description": "Deleted"}}
+                    }
+                },
+                "/api/v1/users": {
+                    "post": {
+                        "operationId": "create_user",
+                        "requestBody": {"content": {"application/json": {
+                            "schema": {"$ref": "#/components/schemas/NewUser"}
+                        }}},
+                        "responses": {
+                            "201": {"content": {"application/json": {
+                                "schema": {"$ref": "#/components/schemas/User"}
+                            }}},
+                            "202": {"content": {"application/json": {
+                                "schema": {"$ref": "#/components/schemas/Accepted"}
+                            }}}
+                        }
+                    }
+                }
+            },
+            "components": {"schemas": {
+                "Status": {"type": "string", "enum": ["ok", "fail"]},
+                "User": {
+                    "type": "object",
+                    "description": "A user",
+                    "required": ["id", "tags"],
+                    "properties": {
+                        "id": {"type": "integer"},
+                        "avatar_url": {"type": ["string", "null"]},
+                        "password_hash": {"type": "string", "writeOnly": true},
+                        "tags": {"type": "array", "items": {"type": "string"}},
+                        "checks": {
+                            "type": "object",
+                            "additionalProperties": {"$ref": "#/components/schemas/Status"}
+                        }
+                    }
+                }
+            }}
+        })
+    }
+
+    #[test]
+    fn test_schemas_become_types() {
+        let ts = generate(&spec(), "/api/v1").unwrap();
+
+        assert!(
+            ts.contains("export type Status = 'ok' | 'fail';\n"),
+            "{}",
+            ts
+        );
+        assert!(ts.contains(
+            "/** A user */\n\
+             export interface User {\n  \
+             avatar_url?: string | null;\n  \
+             checks?: Record<string, Status>;\n  \
+             id: number;\n  \
+             tags: string[];\n\
+             }\n"
+        ));
+        assert!(!ts.contains("password_hash"));
+    }
+
+    #[test]
+    fn test_operations_become_functions() {
+        let ts = generate(&spec(), "/api/v1").unwrap();
+
+        assert!(
+            ts.contains(
+                "  /** Get a user by ID */\n  \
+             getUser: async (userId: number): Promise<User> =>\n    \
+             (await http.get<User>(`/users/${userId}`)).data,\n"
+            ),
+            "{}",
+            ts
+        );
+        assert!(ts.contains(
+            "  deleteUser: async (userId: number, ifMatch: string, xReason?: string): Promise<void> => {\n    \
+             await http.delete(`/users/${userId}`, { headers: { 'If-Match': ifMatch, 'X-Reason': xReason } });\n  },\n"
+        ));
+        assert!(ts.contains(
+            "  createUser: async (body: NewUser): Promise<User | Accepted> =>\n    \
+             (await http.post<User | Accepted>('/users', body)).data,\n"
+        ));
+        // Only operations under the prefix
+        assert!(!ts.contains("live"));
+    }
+}
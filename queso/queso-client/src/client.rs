@@ -0,0 +1,295 @@
+use std::sync::Arc;
+
+use reqwest::{RequestBuilder, Response, StatusCode, header};
+use serde::de::DeserializeOwned;
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::{
+    endpoint::{self, Endpoint, V1_PREFIX},
+    error::{Error, Problem},
+    model::{
+        CreateUserRequest, CurrentUser, EmailLoginRequest, LoginResponse, OAuthCallback,
+        OAuthUrlResponse, Signup, UpdateUserRequest, User,
+    },
+};
+
+#[derive(Clone, Default)]
+struct Session {
+    token: Option<String>,
+    /// Kept after an email login so an expired token can be replaced
+    credentials: Option<EmailLoginRequest>,
+}
+
+/// Client for the v1 API. Cloning is cheap and clones share the session.
+///
+/// Logging in stores the bearer token for later calls. After an email login,
+/// or with [`Client::with_credentials`], the credentials are kept too and a
+/// call rejected with `401` logs in again and is retried once.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    session: Arc<RwLock<Session>>,
+}
+
+impl Client {
+    /// `base_url` is the server root, like `https://queso.example.com`.
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        let base_url = Url::parse(base_url)?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.as_str().trim_end_matches('/').to_string(),
+            session: Arc::default(),
+        })
+    }
+
+    /// Uses `http` for requests, e.g. to set timeouts or a proxy.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Starts with an existing token instead of logging in.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.session_mut().token = Some(token.into());
+        self
+    }
+
+    /// Logs in with these credentials whenever a call is rejected for a
+    /// missing or invalid token, so the first call needs no explicit login.
+    pub fn with_credentials(mut self, email: &str, password: &str) -> Self {
+        self.session_mut().credentials = Some(EmailLoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        });
+        self
+    }
+
+    /// The current bearer token, if logged in.
+    pub async fn token(&self) -> Option<String> {
+        self.session.read().await.token.clone()
+    }
+
+    pub async fn login_with_email(&self, email: &str, password: &str) -> Result<(), Error> {
+        let credentials = EmailLoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        let token = self.login(&credentials).await?;
+
+        let mut session = self.session.write().await;
+        session.token = Some(token);
+        session.credentials = Some(credentials);
+        Ok(())
+    }
+
+    /// The Google consent page URL to send the user to.
+    pub async fn google_login_url(&self) -> Result<String, Error> {
+        let response = self.request(&endpoint::GOOGLE_LOGIN, &[]).send().await?;
+        let body: OAuthUrlResponse = json(response).await?;
+        Ok(body.url)
+    }
+
+    /// Completes a Google login with the parameters Google redirected back
+    /// with.
+    pub async fn login_with_google(&self, code: &str, state: &str) -> Result<(), Error> {
+        let callback = OAuthCallback {
+            code: code.to_string(),
+            state: state.to_string(),
+        };
+        let response = self
+            .request(&endpoint::GOOGLE_CALLBACK, &[])
+            .json(&callback)
+            .send()
+            .await?;
+        let token = token(json(response).await?)?;
+
+        let mut session = self.session.write().await;
+        session.token = Some(token);
+        session.credentials = None;
+        Ok(())
+    }
+
+    pub async fn me(&self) -> Result<CurrentUser, Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::ME, &[]))
+            .await?;
+        json(response).await
+    }
+
+    /// Ends the session on the server and forgets the token and credentials.
+    pub async fn logout(&self) -> Result<(), Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::LOGOUT, &[]))
+            .await?;
+        empty(response).await?;
+
+        *self.session.write().await = Session::default();
+        Ok(())
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>, Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::LIST_USERS, &[]))
+            .await?;
+        json(response).await
+    }
+
+    pub async fn get_user(&self, id: i32) -> Result<User, Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::GET_USER, &[("id", id)]))
+            .await?;
+        json(response).await
+    }
+
+    pub async fn create_user(&self, user: &CreateUserRequest) -> Result<Signup, Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::CREATE_USER, &[]).json(user))
+            .await?;
+        if response.status() == StatusCode::ACCEPTED {
+            return json(response).await.map(Signup::Accepted);
+        }
+        json(response).await.map(Signup::Created)
+    }
+
+    /// Applies `changes` to `user` as last read. Fails with
+    /// [`ErrorCode::PreconditionFailed`](crate::ErrorCode::PreconditionFailed)
+    /// if someone else changed the user since.
+    pub async fn update_user(
+        &self,
+        user: &User,
+        changes: &UpdateUserRequest,
+    ) -> Result<User, Error> {
+        let response = self
+            .authenticated(|| {
+                self.request(&endpoint::UPDATE_USER, &[("id", user.id)])
+                    .header(header::IF_MATCH, etag(user.version))
+                    .json(changes)
+            })
+            .await?;
+        json(response).await
+    }
+
+    /// Deletes `user` unless someone changed it since it was read.
+    pub async fn delete_user(&self, user: &User) -> Result<(), Error> {
+        let response = self
+            .authenticated(|| {
+                self.request(&endpoint::DELETE_USER, &[("id", user.id)])
+                    .header(header::IF_MATCH, etag(user.version))
+            })
+            .await?;
+        empty(response).await
+    }
+
+    /// Users deleted recently enough to be restored.
+    pub async fn list_deleted_users(&self) -> Result<Vec<User>, Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::LIST_DELETED_USERS, &[]))
+            .await?;
+        json(response).await
+    }
+
+    /// Undoes the deletion of a user. Fails with
+    /// [`ErrorCode::UserNotFound`](crate::ErrorCode::UserNotFound) once the
+    /// retention period has passed.
+    pub async fn restore_user(&self, id: i32) -> Result<User, Error> {
+        let response = self
+            .authenticated(|| self.request(&endpoint::RESTORE_USER, &[("id", id)]))
+            .await?;
+        json(response).await
+    }
+
+    /// The session while building. A client already cloned gets its own copy.
+    fn session_mut(&mut self) -> &mut Session {
+        if Arc::get_mut(&mut self.session).is_none() {
+            let current = self
+                .session
+                .try_read()
+                .map(|session| session.clone())
+                .unwrap_or_default();
+            self.session = Arc::new(RwLock::new(current));
+        }
+        Arc::get_mut(&mut self.session)
+            .expect("The session was just replaced")
+            .get_mut()
+    }
+
+    fn request(&self, endpoint: &Endpoint, params: &[(&str, i32)]) -> RequestBuilder {
+        let mut path = endpoint.path.to_string();
+        for (name, value) in params {
+            path = path.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        let url = format!("{}{}{}", self.base_url, V1_PREFIX, path);
+        self.http.request(endpoint.method.clone(), url)
+    }
+
+    async fn login(&self, credentials: &EmailLoginRequest) -> Result<String, Error> {
+        let response = self
+            .request(&endpoint::LOGIN_EMAIL, &[])
+            .json(credentials)
+            .send()
+            .await?;
+        token(json(response).await?)
+    }
+
+    /// Sends the request with the current token, logging in again and
+    /// retrying once if it was rejected and credentials are known.
+    async fn authenticated(&self, request: impl Fn() -> RequestBuilder) -> Result<Response, Error> {
+        let (token, credentials) = {
+            let session = self.session.read().await;
+            (session.token.clone(), session.credentials.clone())
+        };
+        let response = with_token(request(), token.as_deref()).send().await?;
+
+        let Some(credentials) = credentials else {
+            return Ok(response);
+        };
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let token = self.login(&credentials).await?;
+        self.session.write().await.token = Some(token.clone());
+        Ok(with_token(request(), Some(&token)).send().await?)
+    }
+}
+
+fn with_token(request: RequestBuilder, token: Option<&str>) -> RequestBuilder {
+    match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// The server's `ETag` for a user at `version`.
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+fn token(login: LoginResponse) -> Result<String, Error> {
+    login.token.ok_or(Error::MissingToken)
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    let response = check(response).await?;
+    Ok(response.json().await?)
+}
+
+async fn empty(response: Response) -> Result<(), Error> {
+    check(response).await?;
+    Ok(())
+}
+
+/// Turns error statuses into [`Error::Api`].
+async fn check(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await?;
+    let problem = serde_json::from_str::<Problem>(&body)
+        .unwrap_or_else(|_| Problem::from_status(status, body));
+    Err(Error::Api(Box::new(problem)))
+}
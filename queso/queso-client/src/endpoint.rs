@@ -0,0 +1,48 @@
+use reqwest::Method;
+
+/// Prefix of the API version this client speaks.
+pub const V1_PREFIX: &str = "/api/v1";
+
+/// An operation of the v1 API; `path` is relative to [`V1_PREFIX`] and may
+/// contain `{id}`.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub method: Method,
+    pub path: &'static str,
+}
+
+impl Endpoint {
+    const fn new(method: Method, path: &'static str) -> Self {
+        Self { method, path }
+    }
+}
+
+pub const LOGIN_EMAIL: Endpoint = Endpoint::new(Method::POST, "/auth/login/email");
+pub const ME: Endpoint = Endpoint::new(Method::GET, "/auth/me");
+pub const LOGOUT: Endpoint = Endpoint::new(Method::POST, "/auth/logout");
+pub const GOOGLE_LOGIN: Endpoint = Endpoint::new(Method::GET, "/auth/google/login");
+pub const GOOGLE_CALLBACK: Endpoint = Endpoint::new(Method::POST, "/auth/google/callback");
+pub const LIST_USERS: Endpoint = Endpoint::new(Method::GET, "/users");
+pub const CREATE_USER: Endpoint = Endpoint::new(Method::POST, "/users");
+pub const GET_USER: Endpoint = Endpoint::new(Method::GET, "/users/{id}");
+pub const UPDATE_USER: Endpoint = Endpoint::new(Method::PATCH, "/users/{id}");
+pub const DELETE_USER: Endpoint = Endpoint::new(Method::DELETE, "/users/{id}");
+pub const LIST_DELETED_USERS: Endpoint = Endpoint::new(Method::GET, "/admin/users/deleted");
+pub const RESTORE_USER: Endpoint = Endpoint::new(Method::POST, "/admin/users/{id}/restore");
+
+/// Every operation the client implements, checked against the server's
+/// OpenAPI document in the tests.
+pub const ALL: &[Endpoint] = &[
+    LOGIN_EMAIL,
+    ME,
+    LOGOUT,
+    GOOGLE_LOGIN,
+    GOOGLE_CALLBACK,
+    LIST_USERS,
+    CREATE_USER,
+    GET_USER,
+    UPDATE_USER,
+    DELETE_USER,
+    LIST_DELETED_USERS,
+    RESTORE_USER,
+];
@@ -0,0 +1,182 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The server answered with a problem details body.
+    #[error("{0}")]
+    Api(Box<Problem>),
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid base URL: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    /// Login succeeded but no token came back, which happens when the server
+    /// uses cookie sessions.
+    #[error("The server did not return a bearer token")]
+    MissingToken,
+}
+
+impl Error {
+    /// The server's error code, if the server answered at all.
+    pub fn code(&self) -> Option<&ErrorCode> {
+        match self {
+            Error::Api(problem) => Some(&problem.code),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Api(problem) => StatusCode::from_u16(problem.status).ok(),
+            Error::Http(e) => e.status(),
+            _ => None,
+        }
+    }
+}
+
+/// RFC 7807 problem details, as sent with every error response.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: ErrorCode,
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub errors: Vec<FieldError>,
+}
+
+impl Problem {
+    /// Stands in for error responses without a problem body, like those from
+    /// a proxy in front of the server.
+    pub(crate) fn from_status(status: StatusCode, body: String) -> Self {
+        Self {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: body,
+            code: ErrorCode::Other(String::new()),
+            request_id: None,
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.code, self.detail)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+/// The stable `code` of a problem response. Codes this version does not know
+/// about are kept as [`ErrorCode::Other`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum ErrorCode {
+    InvalidCredentials,
+    MissingCredentials,
+    InvalidToken,
+    TokenCreationFailed,
+    OauthError,
+    AccountDeleted,
+    CsrfTokenMismatch,
+    Forbidden,
+    UserNotFound,
+    UsernameExists,
+    EmailExists,
+    PreconditionFailed,
+    PreconditionRequired,
+    ValidationFailed,
+    InvalidBody,
+    InvalidPath,
+    RateLimited,
+    InvalidIdempotencyKey,
+    IdempotencyKeyInUse,
+    IdempotencyKeyReused,
+    DatabaseError,
+    DatabaseUnavailable,
+    PasswordHashError,
+    InternalError,
+    Other(String),
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCode::InvalidCredentials => "invalid_credentials",
+            ErrorCode::MissingCredentials => "missing_credentials",
+            ErrorCode::InvalidToken => "invalid_token",
+            ErrorCode::TokenCreationFailed => "token_creation_failed",
+            ErrorCode::OauthError => "oauth_error",
+            ErrorCode::AccountDeleted => "account_deleted",
+            ErrorCode::CsrfTokenMismatch => "csrf_token_mismatch",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::UserNotFound => "user_not_found",
+            ErrorCode::UsernameExists => "username_exists",
+            ErrorCode::EmailExists => "email_exists",
+            ErrorCode::PreconditionFailed => "precondition_failed",
+            ErrorCode::PreconditionRequired => "precondition_required",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::InvalidBody => "invalid_body",
+            ErrorCode::InvalidPath => "invalid_path",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::InvalidIdempotencyKey => "invalid_idempotency_key",
+            ErrorCode::IdempotencyKeyInUse => "idempotency_key_in_use",
+            ErrorCode::IdempotencyKeyReused => "idempotency_key_reused",
+            ErrorCode::DatabaseError => "database_error",
+            ErrorCode::DatabaseUnavailable => "database_unavailable",
+            ErrorCode::PasswordHashError => "password_hash_error",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::Other(code) => code,
+        }
+    }
+}
+
+impl From<String> for ErrorCode {
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "invalid_credentials" => ErrorCode::InvalidCredentials,
+            "missing_credentials" => ErrorCode::MissingCredentials,
+            "invalid_token" => ErrorCode::InvalidToken,
+            "token_creation_failed" => ErrorCode::TokenCreationFailed,
+            "oauth_error" => ErrorCode::OauthError,
+            "account_deleted" => ErrorCode::AccountDeleted,
+            "csrf_token_mismatch" => ErrorCode::CsrfTokenMismatch,
+            "forbidden" => ErrorCode::Forbidden,
+            "user_not_found" => ErrorCode::UserNotFound,
+            "username_exists" => ErrorCode::UsernameExists,
+            "email_exists" => ErrorCode::EmailExists,
+            "precondition_failed" => ErrorCode::PreconditionFailed,
+            "precondition_required" => ErrorCode::PreconditionRequired,
+            "validation_failed" => ErrorCode::ValidationFailed,
+            "invalid_body" => ErrorCode::InvalidBody,
+            "invalid_path" => ErrorCode::InvalidPath,
+            "rate_limited" => ErrorCode::RateLimited,
+            "invalid_idempotency_key" => ErrorCode::InvalidIdempotencyKey,
+            "idempotency_key_in_use" => ErrorCode::IdempotencyKeyInUse,
+            "idempotency_key_reused" => ErrorCode::IdempotencyKeyReused,
+            "database_error" => ErrorCode::DatabaseError,
+            "database_unavailable" => ErrorCode::DatabaseUnavailable,
+            "password_hash_error" => ErrorCode::PasswordHashError,
+            "internal_error" => ErrorCode::InternalError,
+            _ => ErrorCode::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,23 @@
+//! Typed client for the Queso API.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), queso_client::Error> {
+//! let client = queso_client::Client::new("http://localhost:3000")?;
+//! client.login_with_email("ana@example.com", "correct horse").await?;
+//! let me = client.me().await?;
+//! println!("signed in as {}", me.username);
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+pub mod endpoint;
+mod error;
+mod model;
+
+pub use client::Client;
+pub use error::{Error, ErrorCode, FieldError, Problem};
+pub use model::{
+    CreateUserRequest, CurrentUser, EmailLoginRequest, OAuthCallback, Signup, SignupAccepted,
+    UpdateUserRequest, User,
+};
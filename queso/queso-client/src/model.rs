@@ -0,0 +1,78 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// A user as returned by the users endpoints.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub google_id: Option<String>,
+    pub avatar_url: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// Incremented by every update; sent as `If-Match` when changing the user
+    pub version: i32,
+    pub updated_at: NaiveDateTime,
+    /// Set while the user is deleted but can still be restored
+    #[serde(default)]
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// The signed-in user, as returned by `GET /auth/me`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// Fields to change; `None` leaves a field as it is.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateUserRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// Outcome of a sign-up. Servers in hardened mode accept every sign-up and
+/// report the result by email instead of returning the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signup {
+    Created(User),
+    Accepted(SignupAccepted),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SignupAccepted {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmailLoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthCallback {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct LoginResponse {
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct OAuthUrlResponse {
+    pub url: String,
+}
@@ -0,0 +1,194 @@
+//! The client against the real application, served in-process.
+
+use std::collections::BTreeSet;
+
+use queso_client::{
+    Client, CreateUserRequest, Error, ErrorCode, Signup, UpdateUserRequest, endpoint,
+};
+use queso_server::{
+    config::database::PoolSettings,
+    test_support::{self, TestDatabase, TestServer},
+};
+use reqwest::StatusCode;
+
+fn api_code(result: Result<impl std::fmt::Debug, Error>) -> ErrorCode {
+    match result {
+        Err(Error::Api(problem)) => problem.code,
+        other => panic!("expected a problem response, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_client_covers_the_openapi_document() {
+    let doc: serde_json::Value =
+        serde_json::from_str(&queso_server::openapi::v1().to_json().unwrap()).unwrap();
+    let mut documented = BTreeSet::new();
+    for (path, item) in doc["paths"].as_object().unwrap() {
+        let Some(path) = path.strip_prefix(endpoint::V1_PREFIX) else {
+            continue;
+        };
+        for method in item.as_object().unwrap().keys() {
+            documented.insert((method.to_uppercase(), path.to_string()));
+        }
+    }
+
+    let implemented: BTreeSet<_> = endpoint::ALL
+        .iter()
+        .map(|endpoint| (endpoint.method.to_string(), endpoint.path.to_string()))
+        .collect();
+
+    assert_eq!(implemented, documented);
+}
+
+#[tokio::test]
+async fn test_errors_carry_the_server_codes() {
+    let server = TestServer::start(&test_support::settings(), test_support::offline_pool()).await;
+    let client = Client::new(&server.url()).unwrap();
+
+    assert_eq!(api_code(client.me().await), ErrorCode::MissingCredentials);
+
+    let client = client.with_token("not-a-jwt");
+    assert_eq!(api_code(client.me().await), ErrorCode::InvalidToken);
+
+    match client.login_with_email("", "").await {
+        Err(Error::Api(problem)) => {
+            assert_eq!(problem.code, ErrorCode::ValidationFailed);
+            assert_eq!(problem.status, 422);
+            let fields: Vec<_> = problem.errors.iter().map(|e| e.field.as_str()).collect();
+            assert_eq!(fields, ["email", "password"]);
+        }
+        other => panic!("expected a validation problem, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_sign_up_log_in_and_manage_users() {
+    let db = TestDatabase::start(PoolSettings::default()).await;
+    let server = TestServer::start(&test_support::settings(), db.pool.clone()).await;
+    let client = Client::new(&server.url()).unwrap();
+
+    let request = CreateUserRequest {
+        username: "ana".to_string(),
+        email: "ana@example.com".to_string(),
+        password: "correct horse battery".to_string(),
+    };
+    let Signup::Created(user) = client.create_user(&request).await.unwrap() else {
+        panic!("hardened mode is off in tests");
+    };
+    assert_eq!(user.username, "ana");
+    assert_eq!(
+        api_code(client.create_user(&request).await),
+        ErrorCode::UsernameExists
+    );
+
+    assert_eq!(
+        api_code(client.login_with_email("ana@example.com", "wrong").await),
+        ErrorCode::InvalidCredentials
+    );
+    client
+        .login_with_email("ana@example.com", "correct horse battery")
+        .await
+        .unwrap();
+    assert!(client.token().await.is_some());
+    assert_eq!(client.me().await.unwrap().id, user.id);
+
+    assert_eq!(client.get_user(user.id).await.unwrap(), user);
+    assert_eq!(client.list_users().await.unwrap(), vec![user.clone()]);
+
+    client.logout().await.unwrap();
+    assert!(client.token().await.is_none());
+
+    // Users may only change themselves
+    client
+        .login_with_email("ana@example.com", "correct horse battery")
+        .await
+        .unwrap();
+
+    let renamed = client
+        .update_user(
+            &user,
+            &UpdateUserRequest {
+                username: Some("ana.b".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(renamed.username, "ana.b");
+    assert_eq!(renamed.version, user.version + 1);
+
+    // `user` is stale now
+    assert_eq!(
+        api_code(client.delete_user(&user).await),
+        ErrorCode::PreconditionFailed
+    );
+    client.delete_user(&renamed).await.unwrap();
+    let error = client.get_user(user.id).await.unwrap_err();
+    assert_eq!(error.code(), Some(&ErrorCode::UserNotFound));
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(
+        api_code(client.list_deleted_users().await),
+        ErrorCode::Forbidden
+    );
+
+    // Admins are configured by ID, so serve them once the account exists
+    let request = CreateUserRequest {
+        username: "root".to_string(),
+        email: "root@example.com".to_string(),
+        password: "correct horse battery".to_string(),
+    };
+    let Signup::Created(root) = client.create_user(&request).await.unwrap() else {
+        panic!("hardened mode is off in tests");
+    };
+    let mut settings = test_support::settings();
+    settings.auth.admin_user_ids = vec![root.id];
+    let admin_server = TestServer::start(&settings, db.pool.clone()).await;
+    let admin = Client::new(&admin_server.url()).unwrap();
+    admin
+        .login_with_email("root@example.com", "correct horse battery")
+        .await
+        .unwrap();
+
+    let deleted = admin.list_deleted_users().await.unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert!(deleted[0].deleted_at.is_some());
+    let restored = admin.restore_user(user.id).await.unwrap();
+    assert_eq!(restored.username, "ana.b");
+    assert_eq!(restored.deleted_at, None);
+    assert_eq!(client.get_user(user.id).await.unwrap(), restored);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker"]
+async fn test_rejected_token_is_replaced_by_logging_in_again() {
+    let db = TestDatabase::start(PoolSettings::default()).await;
+    let server = TestServer::start(&test_support::settings(), db.pool.clone()).await;
+    Client::new(&server.url())
+        .unwrap()
+        .create_user(&CreateUserRequest {
+            username: "bob".to_string(),
+            email: "bob@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let client = Client::new(&server.url())
+        .unwrap()
+        .with_credentials("bob@example.com", "correct horse battery")
+        .with_token("expired-or-revoked");
+    assert_eq!(client.me().await.unwrap().username, "bob");
+    assert_ne!(client.token().await.unwrap(), "expired-or-revoked");
+
+    // Without a token the first call logs in
+    let client = Client::new(&server.url())
+        .unwrap()
+        .with_credentials("bob@example.com", "correct horse battery");
+    assert_eq!(client.me().await.unwrap().username, "bob");
+
+    let client = Client::new(&server.url())
+        .unwrap()
+        .with_credentials("bob@example.com", "wrong password");
+    assert_eq!(api_code(client.me().await), ErrorCode::InvalidCredentials);
+}
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX users_email_lower_key;
+DROP INDEX users_username_lower_key;
+
+ALTER TABLE users
+ADD CONSTRAINT users_username_key UNIQUE (username),
+ADD CONSTRAINT users_email_key UNIQUE (email);
@@ -0,0 +1,9 @@
+-- Enforce case-insensitive uniqueness so `Bob@x.com` and `bob@x.com` can't
+-- both register. Index names are matched in `UserError` to report conflicts.
+
+ALTER TABLE users
+DROP CONSTRAINT users_username_key,
+DROP CONSTRAINT users_email_key;
+
+CREATE UNIQUE INDEX users_username_lower_key ON users (lower(username));
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email));
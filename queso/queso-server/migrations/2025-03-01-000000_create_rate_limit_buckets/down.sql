@@ -0,0 +1,2 @@
+DROP FUNCTION rate_limit_take(VARCHAR, DOUBLE PRECISION, DOUBLE PRECISION);
+DROP TABLE rate_limit_buckets;
@@ -0,0 +1,37 @@
+-- Token buckets shared by every server instance
+CREATE TABLE rate_limit_buckets (
+    key VARCHAR PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX rate_limit_buckets_updated_at_idx ON rate_limit_buckets (updated_at);
+
+-- Refills the bucket for the elapsed time and takes one token if available.
+-- The upsert locks the row, so concurrent calls for the same key serialize.
+CREATE FUNCTION rate_limit_take(
+    p_key VARCHAR,
+    p_capacity DOUBLE PRECISION,
+    p_refill_per_sec DOUBLE PRECISION
+) RETURNS TABLE (allowed BOOLEAN, remaining DOUBLE PRECISION) AS $$
+DECLARE
+    available DOUBLE PRECISION;
+BEGIN
+    INSERT INTO rate_limit_buckets AS b (key, tokens, updated_at)
+    VALUES (p_key, p_capacity, clock_timestamp())
+    ON CONFLICT (key) DO UPDATE
+        SET tokens = LEAST(
+                p_capacity,
+                b.tokens + EXTRACT(EPOCH FROM clock_timestamp() - b.updated_at) * p_refill_per_sec
+            ),
+            updated_at = clock_timestamp()
+    RETURNING b.tokens INTO available;
+
+    IF available >= 1 THEN
+        UPDATE rate_limit_buckets SET tokens = available - 1 WHERE key = p_key;
+        RETURN QUERY SELECT TRUE, available - 1;
+    ELSE
+        RETURN QUERY SELECT FALSE, available;
+    END IF;
+END;
+$$ LANGUAGE plpgsql;
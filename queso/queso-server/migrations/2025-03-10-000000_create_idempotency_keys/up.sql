@@ -0,0 +1,13 @@
+-- Responses to requests sent with an `Idempotency-Key`, replayed when the
+-- client retries. A row without a response is a request still running.
+CREATE TABLE idempotency_keys (
+    key VARCHAR PRIMARY KEY,
+    fingerprint VARCHAR NOT NULL,
+    locked_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    response_status SMALLINT,
+    response_headers JSONB,
+    response_body BYTEA
+);
+
+CREATE INDEX idempotency_keys_expires_at_idx ON idempotency_keys (expires_at);
@@ -0,0 +1,7 @@
+-- Every update bumps `version`, which is sent as the user's ETag so
+-- concurrent edits can be detected with `If-Match`.
+ALTER TABLE users
+ADD COLUMN version INTEGER NOT NULL DEFAULT 1,
+ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+UPDATE users SET updated_at = created_at;
@@ -0,0 +1,4 @@
+DELETE FROM users WHERE deleted_at IS NOT NULL;
+
+DROP INDEX users_deleted_at_idx;
+ALTER TABLE users DROP COLUMN deleted_at;
@@ -0,0 +1,5 @@
+-- Deleted users keep their row, and their username and email, until the
+-- retention period ends and the purge worker removes them.
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX users_deleted_at_idx ON users (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,16 @@
+-- Deleted users sharing a username or email with another user are dropped,
+-- as the full indexes cannot hold both
+DELETE FROM users deleted
+WHERE deleted.deleted_at IS NOT NULL
+  AND EXISTS (
+    SELECT 1 FROM users other
+    WHERE other.id <> deleted.id
+      AND (lower(other.username) = lower(deleted.username)
+        OR lower(other.email) = lower(deleted.email))
+  );
+
+DROP INDEX users_username_lower_key;
+DROP INDEX users_email_lower_key;
+
+CREATE UNIQUE INDEX users_username_lower_key ON users (lower(username));
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email));
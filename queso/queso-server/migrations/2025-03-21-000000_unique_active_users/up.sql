@@ -0,0 +1,9 @@
+-- Only users that are not deleted hold their username and email, so both can
+-- be signed up with again as soon as an account is deleted. Restoring it then
+-- conflicts on the same indexes, which `UserError` already maps.
+
+DROP INDEX users_username_lower_key;
+DROP INDEX users_email_lower_key;
+
+CREATE UNIQUE INDEX users_username_lower_key ON users (lower(username)) WHERE deleted_at IS NULL;
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email)) WHERE deleted_at IS NULL;
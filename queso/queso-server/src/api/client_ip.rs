@@ -0,0 +1,75 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{HeaderMap, HeaderName},
+};
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// The address of the client that sent `req`, trusting `X-Forwarded-For`
+/// from `hops` proxies in front of the server. `None` when the connection's
+/// address is not known, as in tests that call the router directly.
+pub fn client_ip(req: &Request, hops: usize) -> Option<IpAddr> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    forwarded_for(req.headers(), peer, hops)
+}
+
+/// The client address as seen by the outermost trusted proxy.
+///
+/// Each trusted proxy appends the address it received the request from, so
+/// with `hops` proxies the client is the `hops`-th entry from the right;
+/// anything further left was supplied by the client and is ignored. Falls
+/// back to the peer address when the header is missing or too short.
+fn forwarded_for(headers: &HeaderMap, peer: Option<IpAddr>, hops: usize) -> Option<IpAddr> {
+    if hops == 0 {
+        return peer;
+    }
+
+    let forwarded: Vec<&str> = headers
+        .get_all(&X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    forwarded
+        .len()
+        .checked_sub(hops)
+        .and_then(|index| forwarded[index].parse().ok())
+        .or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_client_ip_respects_trusted_hops() {
+        let peer: Option<IpAddr> = Some("10.0.0.2".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            &X_FORWARDED_FOR,
+            HeaderValue::from_static("6.6.6.6, 203.0.113.7"),
+        );
+
+        assert_eq!(forwarded_for(&headers, peer, 0), peer);
+        assert_eq!(
+            forwarded_for(&headers, peer, 1),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            forwarded_for(&headers, peer, 2),
+            Some("6.6.6.6".parse().unwrap())
+        );
+        // More hops than entries: the header cannot be trusted
+        assert_eq!(forwarded_for(&headers, peer, 3), peer);
+        assert_eq!(forwarded_for(&HeaderMap::new(), peer, 1), peer);
+    }
+}
@@ -0,0 +1,182 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header, request::Parts},
+};
+
+use super::error::ApiError;
+
+/// The `ETag` of a resource at `version`, e.g. `"3"`.
+pub fn etag(version: i32) -> HeaderValue {
+    HeaderValue::try_from(format!("\"{}\"", version))
+        .expect("A quoted number is a valid header value")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EntityTag {
+    weak: bool,
+    opaque: String,
+}
+
+/// The tags listed in a precondition header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tags {
+    /// `*`, matching any current representation
+    Any,
+    List(Vec<EntityTag>),
+}
+
+impl Tags {
+    /// `None` when the header is absent. Malformed entries are skipped, so
+    /// they match nothing.
+    fn parse(headers: &HeaderMap, name: &HeaderName) -> Option<Self> {
+        let mut values = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .peekable();
+        values.peek()?;
+
+        let mut tags = Vec::new();
+        for value in values {
+            if value == "*" {
+                return Some(Tags::Any);
+            }
+            let (weak, tag) = match value.strip_prefix("W/") {
+                Some(tag) => (true, tag),
+                None => (false, value),
+            };
+            if let Some(opaque) = tag.strip_prefix('"').and_then(|tag| tag.strip_suffix('"')) {
+                tags.push(EntityTag {
+                    weak,
+                    opaque: opaque.to_string(),
+                });
+            }
+        }
+        Some(Tags::List(tags))
+    }
+}
+
+/// The required `If-Match` header of an update or delete. Requests without
+/// it are rejected with `428 Precondition Required`, so a client cannot
+/// overwrite changes it has not seen by accident.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfMatch(Tags);
+
+impl IfMatch {
+    /// The versions the change may apply to, or `None` for `*`. Weak tags
+    /// never match, as `If-Match` uses strong comparison.
+    pub fn versions(&self) -> Option<Vec<i32>> {
+        match &self.0 {
+            Tags::Any => None,
+            Tags::List(tags) => Some(
+                tags.iter()
+                    .filter(|tag| !tag.weak)
+                    .filter_map(|tag| tag.opaque.parse().ok())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Tags::parse(&parts.headers, &header::IF_MATCH)
+            .map(IfMatch)
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::PRECONDITION_REQUIRED,
+                    "precondition_required",
+                    "Send the ETag from your last read in the If-Match header",
+                )
+            })
+    }
+}
+
+/// The optional `If-None-Match` header of a read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfNoneMatch(Option<Tags>);
+
+impl IfNoneMatch {
+    /// Whether the client already has `version`, so `304 Not Modified` can
+    /// be sent instead. Uses weak comparison.
+    pub fn matches(&self, version: i32) -> bool {
+        match &self.0 {
+            None => false,
+            Some(Tags::Any) => true,
+            Some(Tags::List(tags)) => {
+                let version = version.to_string();
+                tags.iter().any(|tag| tag.opaque == version)
+            }
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(IfNoneMatch(Tags::parse(
+            &parts.headers,
+            &header::IF_NONE_MATCH,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: HeaderName, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name.clone(), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn if_match(values: &[&'static str]) -> Option<IfMatch> {
+        Tags::parse(&headers(header::IF_MATCH, values), &header::IF_MATCH).map(IfMatch)
+    }
+
+    fn if_none_match(values: &[&'static str]) -> IfNoneMatch {
+        IfNoneMatch(Tags::parse(
+            &headers(header::IF_NONE_MATCH, values),
+            &header::IF_NONE_MATCH,
+        ))
+    }
+
+    #[test]
+    fn test_etag_quotes_the_version() {
+        assert_eq!(etag(3), "\"3\"");
+    }
+
+    #[test]
+    fn test_if_match_uses_strong_comparison() {
+        assert_eq!(if_match(&[]), None);
+        assert_eq!(if_match(&["*"]).unwrap().versions(), None);
+        assert_eq!(
+            if_match(&["\"1\", W/\"2\"", "\"3\""]).unwrap().versions(),
+            Some(vec![1, 3])
+        );
+        // Unquoted or foreign tags match nothing
+        assert_eq!(
+            if_match(&["1", "\"abc\""]).unwrap().versions(),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_if_none_match_uses_weak_comparison() {
+        assert!(!if_none_match(&[]).matches(2));
+        assert!(if_none_match(&["*"]).matches(2));
+        assert!(if_none_match(&["\"1\", W/\"2\""]).matches(2));
+        assert!(!if_none_match(&["\"1\""]).matches(2));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{
+    api::{
+        idempotency::{IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED},
+        rate_limit::{RATE_LIMIT_LIMIT, RATE_LIMIT_POLICY, RATE_LIMIT_REMAINING, RATE_LIMIT_RESET},
+        request_id::REQUEST_ID_HEADER,
+        version::{DEPRECATION, SUNSET},
+    },
+    config::settings::CorsSettings,
+    features::auth::session::CSRF_HEADER,
+};
+
+/// An allowed origin: either exact, like `https://app.example.com`, or every
+/// subdomain of a host, like `https://*.example.com`. Scheme and port must
+/// always match; the wildcard does not cover the bare domain itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginPattern {
+    Exact(String),
+    Subdomains {
+        scheme: String,
+        /// `.example.com`, including the port if one was given
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    pub fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            OriginPattern::Exact(allowed) => origin == *allowed,
+            OriginPattern::Subdomains { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|rest| rest.strip_prefix("://"))
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && !subdomain.starts_with('.')
+                        && subdomain
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'))
+                }),
+        }
+    }
+}
+
+impl FromStr for OriginPattern {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().trim_end_matches('/').to_ascii_lowercase();
+        let Some((scheme, host)) = value.split_once("://") else {
+            return Err(format!(
+                "{:?} is not an origin like https://example.com",
+                value
+            ));
+        };
+        if scheme != "http" && scheme != "https" {
+            return Err(format!("{:?} must use http or https", value));
+        }
+
+        // The wildcard is checked against a concrete host so the rest of the
+        // pattern gets the same validation as an exact origin
+        let (wildcard, concrete) = match host.strip_prefix("*.") {
+            Some(domain) => (true, format!("{}://wildcard.{}", scheme, domain)),
+            None => (false, value.clone()),
+        };
+        if concrete.contains('*') {
+            return Err(format!(
+                "{:?} may only use a wildcard for the leftmost label",
+                value
+            ));
+        }
+        let parsed = url::Url::parse(&concrete).map_err(|e| format!("{:?}: {}", value, e))?;
+        if parsed.origin().ascii_serialization() != concrete {
+            return Err(format!(
+                "{:?} must be a bare origin without path or query",
+                value
+            ));
+        }
+
+        if wildcard {
+            Ok(OriginPattern::Subdomains {
+                scheme: scheme.to_string(),
+                suffix: host[1..].to_string(),
+            })
+        } else {
+            Ok(OriginPattern::Exact(value))
+        }
+    }
+}
+
+impl fmt::Display for OriginPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OriginPattern::Exact(origin) => f.write_str(origin),
+            OriginPattern::Subdomains { scheme, suffix } => write!(f, "{}://*{}", scheme, suffix),
+        }
+    }
+}
+
+/// Request headers browsers may send cross-origin.
+fn allowed_headers() -> Vec<HeaderName> {
+    vec![
+        header::AUTHORIZATION,
+        header::CONTENT_TYPE,
+        header::ACCEPT,
+        REQUEST_ID_HEADER.clone(),
+        CSRF_HEADER.clone(),
+        IDEMPOTENCY_KEY.clone(),
+        header::IF_MATCH,
+        header::IF_NONE_MATCH,
+    ]
+}
+
+/// Response headers readable by cross-origin scripts.
+fn exposed_headers() -> Vec<HeaderName> {
+    vec![
+        REQUEST_ID_HEADER.clone(),
+        header::RETRY_AFTER,
+        RATE_LIMIT_LIMIT.clone(),
+        RATE_LIMIT_REMAINING.clone(),
+        RATE_LIMIT_RESET.clone(),
+        RATE_LIMIT_POLICY.clone(),
+        DEPRECATION.clone(),
+        SUNSET.clone(),
+        header::LINK,
+        IDEMPOTENT_REPLAYED.clone(),
+        header::ETAG,
+    ]
+}
+
+/// CORS for the configured origins only. Requests from other origins get no
+/// CORS headers, so browsers refuse to hand the response to the page.
+pub fn cors_layer(settings: &CorsSettings) -> CorsLayer {
+    let origins = Arc::new(settings.allowed_origins.clone());
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(
+            move |origin: &HeaderValue, _parts| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| origins.iter().any(|pattern| pattern.matches(origin)))
+            },
+        ))
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(allowed_headers())
+        .expose_headers(exposed_headers())
+        .allow_credentials(settings.allow_credentials)
+        .max_age(settings.max_age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+    };
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn pattern(value: &str) -> OriginPattern {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_origin_patterns() {
+        let exact = pattern("https://App.example.com/");
+        assert!(exact.matches("https://app.example.com"));
+        assert!(!exact.matches("http://app.example.com"));
+        assert!(!exact.matches("https://app.example.com.evil.io"));
+
+        let wildcard = pattern("https://*.example.com");
+        assert_eq!(wildcard.to_string(), "https://*.example.com");
+        assert!(wildcard.matches("https://app.example.com"));
+        assert!(wildcard.matches("https://preview-12.staging.example.com"));
+        assert!(!wildcard.matches("https://example.com"));
+        assert!(!wildcard.matches("https://evilexample.com"));
+        assert!(!wildcard.matches("https://app.example.com:8443"));
+        assert!(!wildcard.matches("http://app.example.com"));
+
+        let with_port = pattern("http://*.localhost:5173");
+        assert!(with_port.matches("http://ui.localhost:5173"));
+        assert!(!with_port.matches("http://ui.localhost"));
+
+        for invalid in [
+            "*",
+            "example.com",
+            "ftp://example.com",
+            "https://example.com/app",
+            "https://app.*.example.com",
+            "https://*",
+        ] {
+            assert!(
+                invalid.parse::<OriginPattern>().is_err(),
+                "{:?} should be rejected",
+                invalid
+            );
+        }
+    }
+
+    fn app() -> Router {
+        let settings = CorsSettings {
+            allowed_origins: vec![
+                pattern("http://localhost:5173"),
+                pattern("https://*.queso.dev"),
+            ],
+            allow_credentials: true,
+            max_age: Duration::from_secs(600),
+        };
+        Router::new()
+            .route("/api/users", get(|| async { "users" }))
+            .layer(cors_layer(&settings))
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/users")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origin_preflight() {
+        for origin in ["http://localhost:5173", "https://pr-7.queso.dev"] {
+            let response = app().oneshot(preflight(origin)).await.unwrap();
+            let headers = response.headers();
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+            assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+            assert!(
+                headers[header::ACCESS_CONTROL_ALLOW_METHODS]
+                    .to_str()
+                    .unwrap()
+                    .contains("DELETE")
+            );
+            assert!(
+                headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+                    .to_str()
+                    .unwrap()
+                    .contains("authorization")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_origins_get_no_cors_headers() {
+        for origin in [
+            "https://evil.example",
+            "https://queso.dev",
+            "http://localhost:3001",
+        ] {
+            let response = app().oneshot(preflight(origin)).await.unwrap();
+            assert!(
+                !response
+                    .headers()
+                    .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+                "{} was allowed",
+                origin
+            );
+
+            let request = Request::get("/api/users")
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap();
+            let response = app().oneshot(request).await.unwrap();
+            assert!(
+                !response
+                    .headers()
+                    .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origin_can_read_exposed_headers() {
+        let request = Request::get("/api/users")
+            .header(header::ORIGIN, "http://localhost:5173")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        let headers = response.headers();
+
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:5173"
+        );
+        let exposed = headers[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap();
+        assert!(exposed.contains("x-request-id"));
+        assert!(exposed.contains("ratelimit-remaining"));
+        assert!(headers[header::VARY].to_str().unwrap().contains("origin"));
+    }
+}
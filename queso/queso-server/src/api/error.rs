@@ -0,0 +1,257 @@
+use axum::{
+    Json,
+    extract::rejection::{JsonRejection, PathRejection},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::{request_id::RequestId, validation::FieldError};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Seconds clients are asked to wait before retrying a `503`.
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+/// The error type returned by every handler. Rendered as an RFC 7807
+/// `application/problem+json` body carrying a stable machine-readable `code`.
+///
+/// `detail` is shown to clients and must never contain internal information;
+/// the underlying cause goes in `internal`, which is only ever logged, tagged
+/// with the request id so support can correlate it with the response.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    detail: String,
+    internal: Option<String>,
+    errors: Vec<FieldError>,
+    headers: HeaderMap,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            detail: detail.into(),
+            internal: None,
+            errors: Vec::new(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    pub fn bad_request(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, detail)
+    }
+
+    pub fn unauthorized(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, code, detail)
+    }
+
+    pub fn forbidden(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, code, detail)
+    }
+
+    pub fn not_found(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, detail)
+    }
+
+    pub fn conflict(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, detail)
+    }
+
+    pub fn internal(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, detail)
+    }
+
+    /// A transient failure; tells the client when to retry via `Retry-After`.
+    pub fn service_unavailable(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, code, detail)
+            .with_header(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS))
+    }
+
+    /// Adds a header to the rendered response.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Records the underlying cause for the logs without exposing it to the
+    /// client.
+    pub fn with_internal(mut self, cause: impl std::fmt::Display) -> Self {
+        self.internal = Some(cause.to_string());
+        self
+    }
+
+    /// Attaches per-field errors, e.g. from request validation.
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.code, self.detail)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// RFC 7807 problem details body shared by all error responses.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// URI identifying the problem type, derived from `code`
+    #[serde(rename = "type")]
+    #[schema(example = "urn:queso:problem:user_not_found")]
+    pub problem_type: String,
+    /// Short, human-readable summary of the status
+    #[schema(example = "Not Found")]
+    pub title: String,
+    #[schema(example = 404)]
+    pub status: u16,
+    /// Human-readable explanation specific to this occurrence
+    #[schema(example = "User not found")]
+    pub detail: String,
+    /// Stable machine-readable error code
+    #[schema(example = "user_not_found")]
+    pub code: String,
+    /// Correlates the response with server logs
+    pub request_id: Option<String>,
+    /// Per-field errors, present for validation failures
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    fn log(&self, request_id: Option<&RequestId>) {
+        let request_id = request_id.map(RequestId::as_str).unwrap_or("-");
+        let internal = self.internal.as_deref().unwrap_or("-");
+
+        if self.status.is_server_error() {
+            tracing::error!(
+                request_id,
+                status = self.status.as_u16(),
+                code = self.code,
+                internal,
+                "{}",
+                self.detail
+            );
+        } else if self.internal.is_some() {
+            tracing::debug!(
+                request_id,
+                status = self.status.as_u16(),
+                code = self.code,
+                internal,
+                "{}",
+                self.detail
+            );
+        }
+    }
+
+    fn into_problem(self, request_id: Option<RequestId>) -> ProblemDetails {
+        ProblemDetails {
+            problem_type: format!("urn:queso:problem:{}", self.code),
+            title: self
+                .status
+                .canonical_reason()
+                .unwrap_or("Unknown Error")
+                .to_string(),
+            status: self.status.as_u16(),
+            detail: self.detail,
+            code: self.code.to_string(),
+            request_id: request_id.map(|id| id.to_string()),
+            errors: self.errors,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(mut self) -> Response {
+        let status = self.status;
+        let request_id = RequestId::current();
+        self.log(request_id.as_ref());
+        let headers = std::mem::take(&mut self.headers);
+        let body = self.into_problem(request_id);
+
+        (
+            status,
+            headers,
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), "invalid_body", rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(rejection.status(), "invalid_path", rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::request_id::{REQUEST_ID_HEADER, request_id};
+    use axum::{Router, body::Body, extract::Request, middleware, routing::get};
+    use tower::ServiceExt;
+
+    async fn missing() -> Result<(), ApiError> {
+        Err(ApiError::not_found("user_not_found", "User not found"))
+    }
+
+    async fn send(request: Request) -> (Response, serde_json::Value) {
+        let app = Router::new()
+            .route("/missing", get(missing))
+            .layer(middleware::from_fn(request_id));
+        let response = app.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap();
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    #[tokio::test]
+    async fn test_error_renders_problem_json_with_request_id() {
+        let (response, body) = send(Request::get("/missing").body(Body::empty()).unwrap()).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        assert_eq!(body["type"], "urn:queso:problem:user_not_found");
+        assert_eq!(body["title"], "Not Found");
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["code"], "user_not_found");
+
+        let header_id = response.headers()[&REQUEST_ID_HEADER].to_str().unwrap();
+        assert_eq!(body["request_id"], header_id);
+    }
+
+    #[tokio::test]
+    async fn test_client_request_id_is_propagated() {
+        let request = Request::get("/missing")
+            .header(&REQUEST_ID_HEADER, "client-supplied-id")
+            .body(Body::empty())
+            .unwrap();
+        let (response, body) = send(request).await;
+
+        assert_eq!(response.headers()[&REQUEST_ID_HEADER], "client-supplied-id");
+        assert_eq!(body["request_id"], "client-supplied-id");
+    }
+}
@@ -0,0 +1,8 @@
+use axum::extract::FromRequestParts;
+
+use super::error::ApiError;
+
+/// `axum::extract::Path` whose rejection is rendered as problem details.
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
@@ -0,0 +1,538 @@
+pub mod store;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, Method, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use tracing::Instrument;
+
+pub use store::{Begin, IdempotencyStore, MemoryStore, PostgresStore, StoredResponse};
+
+use crate::{
+    api::{API_PREFIX, ApiError, client_ip::client_ip},
+    features::auth::{model::Keys, session},
+    shutdown::Shutdown,
+};
+
+pub static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// Set on responses replayed from an earlier request with the same key.
+pub static IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LEN: usize = 255;
+
+/// Bodies of requests with a key are buffered to fingerprint them; larger
+/// ones are rejected, matching axum's default body limit.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// A request still running after this long is assumed lost, e.g. with the
+/// instance that ran it, and a retry may run it again.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The only response headers recorded and replayed. Others, like
+/// `Set-Cookie` or `X-Request-Id`, carry credentials or belong to the first
+/// request alone.
+const RECORDED_HEADERS: [HeaderName; 3] = [header::CONTENT_TYPE, header::LOCATION, header::ETAG];
+
+fn is_mutating(method: &Method) -> bool {
+    matches!(*method, Method::POST | Method::PATCH | Method::DELETE)
+}
+
+/// Replays responses to `POST`, `PATCH` and `DELETE` API requests sent with
+/// an `Idempotency-Key`, applied by the [`idempotency`] middleware.
+///
+/// Keys are scoped to the authenticated user, or to the client's address for
+/// anonymous callers, and kept for `ttl`. The request is fingerprinted by method, path
+/// and body, so reusing a key for a different request is rejected rather
+/// than answered with the wrong response. Server errors are not recorded, so
+/// a retry after one runs the request again.
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    keys: Option<Arc<Keys>>,
+    trusted_proxy_hops: usize,
+    ttl: Duration,
+}
+
+impl Idempotency {
+    pub fn new(store: impl IdempotencyStore + 'static, ttl: Duration) -> Self {
+        Self {
+            store: Arc::new(store),
+            keys: None,
+            trusted_proxy_hops: 0,
+            ttl,
+        }
+    }
+
+    /// Keys used to scope idempotency keys to users; without them every
+    /// caller is scoped by address.
+    pub fn with_keys(mut self, keys: Arc<Keys>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Number of reverse proxies whose `X-Forwarded-For` entries are trusted
+    /// to identify anonymous callers.
+    pub fn with_trusted_proxy_hops(mut self, hops: usize) -> Self {
+        self.trusted_proxy_hops = hops;
+        self
+    }
+
+    fn scoped_key(&self, key: &str, req: &Request) -> String {
+        let user_id = self.keys.as_ref().and_then(|keys| {
+            session::credential(req.headers())
+                .and_then(|credential| keys.verify(credential.token()).ok())
+                .map(|claims| claims.user_id)
+        });
+
+        match user_id {
+            Some(user_id) => format!("user:{}:{}", user_id, key),
+            None => match client_ip(req, self.trusted_proxy_hops) {
+                Some(ip) => format!("anonymous:{}:{}", ip, key),
+                None => format!("anonymous:unknown:{}", key),
+            },
+        }
+    }
+}
+
+fn valid_key(value: &HeaderValue) -> Option<&str> {
+    value
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+}
+
+/// Identifies the request a key was first used for.
+fn fingerprint(method: &Method, uri: &Uri, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b"\n");
+    hasher.update(uri.path_and_query().map_or("", |path| path.as_str()));
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let headers = response.headers_mut();
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value))
+            && RECORDED_HEADERS.contains(&name)
+        {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(
+        IDEMPOTENT_REPLAYED.clone(),
+        HeaderValue::from_static("true"),
+    );
+    response
+}
+
+async fn release(store: &dyn IdempotencyStore, key: &str) {
+    if let Err(e) = store.release(key).await {
+        tracing::warn!(error = %e, "Failed to release idempotency key");
+    }
+}
+
+/// Runs a request with a new key and records its response, or answers a
+/// retry with the recorded one. A retry while the first request is still
+/// running gets `409 Conflict`; a key reused for a different request gets
+/// `422 Unprocessable Entity`. If the store fails the request runs anyway.
+pub async fn idempotency(
+    State(idempotency): State<Arc<Idempotency>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !is_mutating(req.method()) || !req.uri().path().starts_with(API_PREFIX) {
+        return next.run(req).await;
+    }
+    let Some(value) = req.headers().get(&IDEMPOTENCY_KEY) else {
+        return next.run(req).await;
+    };
+    let Some(key) = valid_key(value) else {
+        return ApiError::bad_request(
+            "invalid_idempotency_key",
+            format!(
+                "Idempotency-Key must be 1 to {} visible ASCII characters",
+                MAX_KEY_LEN
+            ),
+        )
+        .into_response();
+    };
+    let key = idempotency.scoped_key(key, &req);
+
+    let (parts, body) = req.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_BODY_BYTES).await else {
+        return ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "invalid_body",
+            "Request body is too large",
+        )
+        .into_response();
+    };
+    let fingerprint = fingerprint(&parts.method, &parts.uri, &body);
+    let req = Request::from_parts(parts, Body::from(body));
+
+    let store = &idempotency.store;
+    match store
+        .begin(&key, &fingerprint, idempotency.ttl, LOCK_TIMEOUT)
+        .await
+    {
+        Ok(Begin::Started) => {}
+        Ok(Begin::Completed(stored)) => return replay(stored),
+        Ok(Begin::InProgress) => {
+            return ApiError::conflict(
+                "idempotency_key_in_use",
+                "A request with this Idempotency-Key is still being processed",
+            )
+            .with_header(header::RETRY_AFTER, HeaderValue::from(1))
+            .into_response();
+        }
+        Ok(Begin::Mismatch) => {
+            return ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "idempotency_key_reused",
+                "This Idempotency-Key was already used for a different request",
+            )
+            .into_response();
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "Idempotency store failed, running request");
+            return next.run(req).await;
+        }
+    }
+
+    // Run in a task of its own, so a client that disconnects midway doesn't
+    // leave the key locked until LOCK_TIMEOUT
+    let task =
+        tokio::spawn(run_and_record(idempotency.clone(), key.clone(), req, next).in_current_span());
+    match task.await {
+        Ok(response) => response,
+        Err(e) => {
+            release(idempotency.store.as_ref(), &key).await;
+            if e.is_panic() {
+                std::panic::resume_unwind(e.into_panic());
+            }
+            ApiError::internal("internal_error", "An internal error occurred")
+                .with_internal(e)
+                .into_response()
+        }
+    }
+}
+
+/// Runs the request that took `key` and records its response, or releases
+/// the key if the response should not be replayed.
+async fn run_and_record(
+    idempotency: Arc<Idempotency>,
+    key: String,
+    req: Request,
+    next: Next,
+) -> Response {
+    let store = idempotency.store.as_ref();
+    let response = next.run(req).await;
+    if response.status().is_server_error() {
+        release(store, &key).await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            release(store, &key).await;
+            return ApiError::internal("internal_error", "An internal error occurred")
+                .with_internal(e)
+                .into_response();
+        }
+    };
+
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        headers: parts
+            .headers
+            .iter()
+            .filter(|(name, _)| RECORDED_HEADERS.contains(name))
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect(),
+        body: body.to_vec(),
+    };
+    if let Err(e) = store.complete(&key, &stored).await {
+        tracing::warn!(error = %e, "Failed to record idempotent response");
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Periodically deletes expired keys from the Postgres store until shutdown.
+pub fn spawn_purge_worker(store: PostgresStore, shutdown: &Shutdown) {
+    let worker = shutdown.clone();
+    shutdown.spawn(async move {
+        loop {
+            tokio::select! {
+                _ = worker.cancelled() => break,
+                _ = tokio::time::sleep(PURGE_INTERVAL) => {}
+            }
+
+            match store.purge_expired().await {
+                Ok(purged) => tracing::debug!(purged, "Purged expired idempotency keys"),
+                Err(e) => tracing::warn!(error = %e, "Failed to purge idempotency keys"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::auth::model::Claims;
+    use axum::{
+        Router,
+        extract::ConnectInfo,
+        middleware,
+        routing::{delete, post},
+    };
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    /// Creates numbered users; `/api/users/fail` fails like a lost database.
+    fn app(idempotency: Idempotency) -> (Router, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let created = calls.clone();
+        let failed = calls.clone();
+        let users = Router::new()
+            .route(
+                "/",
+                post(move |body: String| async move {
+                    let id = created.fetch_add(1, Ordering::SeqCst) + 1;
+                    (
+                        StatusCode::CREATED,
+                        [
+                            (header::LOCATION, format!("/api/users/{}", id)),
+                            (header::SET_COOKIE, "session=secret".to_string()),
+                            (HeaderName::from_static("x-request-id"), id.to_string()),
+                        ],
+                        format!("{}:{}", id, body),
+                    )
+                }),
+            )
+            .route(
+                "/fail",
+                delete(move || async move {
+                    failed.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::SERVICE_UNAVAILABLE
+                }),
+            );
+
+        let app = Router::new()
+            .nest("/api/users", users)
+            .layer(middleware::from_fn_with_state(
+                Arc::new(idempotency),
+                super::idempotency,
+            ));
+        (app, calls)
+    }
+
+    fn request(method: Method, uri: &str, key: Option<&str>, body: &str) -> Request {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        if let Some(key) = key {
+            req.headers_mut()
+                .insert(&IDEMPOTENCY_KEY, HeaderValue::from_str(key).unwrap());
+        }
+        req
+    }
+
+    fn from(ip: &str, mut req: Request) -> Request {
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(ip.parse().unwrap(), 40000)));
+        req
+    }
+
+    async fn text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retries_replay_the_first_response() {
+        let (app, calls) = app(Idempotency::new(MemoryStore::new(), TTL));
+        let create = || request(Method::POST, "/api/users", Some("k1"), "ana");
+
+        let first = app.clone().oneshot(create()).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(!first.headers().contains_key(&IDEMPOTENT_REPLAYED));
+        assert_eq!(text(first).await, "1:ana");
+
+        let retry = app.clone().oneshot(create()).await.unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()[header::LOCATION], "/api/users/1");
+        assert_eq!(retry.headers()[&IDEMPOTENT_REPLAYED], "true");
+        // Cookies and per-request headers are not recorded
+        assert!(!retry.headers().contains_key(header::SET_COOKIE));
+        assert!(!retry.headers().contains_key("x-request-id"));
+        assert_eq!(text(retry).await, "1:ana");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Without a key every request runs
+        for _ in 0..2 {
+            app.clone()
+                .oneshot(request(Method::POST, "/api/users", None, "bo"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_keys_are_scoped_to_the_client() {
+        let (app, calls) = app(Idempotency::new(MemoryStore::new(), TTL));
+        let create = |ip| from(ip, request(Method::POST, "/api/users", Some("k1"), "ana"));
+
+        for ip in ["203.0.113.7", "198.51.100.4", "203.0.113.7"] {
+            app.clone().oneshot(create(ip)).await.unwrap();
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_requests_still_record_their_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let started = Arc::new(Notify::new());
+        let proceed = Arc::new(Notify::new());
+        let handler = (calls.clone(), started.clone(), proceed.clone());
+        let app = Router::new()
+            .route(
+                "/api/users",
+                post(move || async move {
+                    let (calls, started, proceed) = handler;
+                    started.notify_one();
+                    proceed.notified().await;
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::CREATED
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                Arc::new(Idempotency::new(MemoryStore::new(), TTL)),
+                super::idempotency,
+            ));
+        let create = || request(Method::POST, "/api/users", Some("k1"), "ana");
+
+        // The client goes away while the handler is running
+        tokio::select! {
+            _ = app.clone().oneshot(create()) => panic!("request finished early"),
+            _ = started.notified() => {}
+        }
+        proceed.notify_one();
+
+        let mut retry = app.clone().oneshot(create()).await.unwrap();
+        for _ in 0..50 {
+            if retry.status() != StatusCode::CONFLICT {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            retry = app.clone().oneshot(create()).await.unwrap();
+        }
+
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()[&IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_key_reuse_with_another_payload_is_rejected() {
+        let (app, calls) = app(Idempotency::new(MemoryStore::new(), TTL));
+
+        app.clone()
+            .oneshot(request(Method::POST, "/api/users", Some("k1"), "ana"))
+            .await
+            .unwrap();
+        let response = app
+            .oneshot(request(Method::POST, "/api/users", Some("k1"), "bo"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(text(response).await.contains("idempotency_key_reused"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_not_recorded() {
+        let (app, calls) = app(Idempotency::new(MemoryStore::new(), TTL));
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(request(Method::DELETE, "/api/users/fail", Some("k1"), ""))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_keys_are_rejected() {
+        let (app, calls) = app(Idempotency::new(MemoryStore::new(), TTL));
+        let key = "k".repeat(MAX_KEY_LEN + 1);
+
+        let response = app
+            .oneshot(request(Method::POST, "/api/users", Some(&key), "ana"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(text(response).await.contains("invalid_idempotency_key"));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_keys_are_scoped_to_users() {
+        let keys = Arc::new(Keys::new(b"idempotency-test-secret"));
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &Claims::new(42),
+            &keys.encoding,
+        )
+        .unwrap();
+        let idempotency = Idempotency::new(MemoryStore::new(), TTL).with_keys(keys);
+
+        let mut req = request(Method::POST, "/api/users", None, "");
+        assert_eq!(idempotency.scoped_key("k1", &req), "anonymous:unknown:k1");
+
+        req = from("203.0.113.7", req);
+        assert_eq!(
+            idempotency.scoped_key("k1", &req),
+            "anonymous:203.0.113.7:k1"
+        );
+
+        req.headers_mut().insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        assert_eq!(idempotency.scoped_key("k1", &req), "user:42:k1");
+    }
+}
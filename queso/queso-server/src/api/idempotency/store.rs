@@ -0,0 +1,350 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Double, Varchar},
+};
+
+use crate::{
+    config::database::{DbError, DbPool, with_connection},
+    schema::idempotency_keys,
+};
+
+/// A response recorded for replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// What [`IdempotencyStore::begin`] found for a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Begin {
+    /// The key is new or expired and is now locked; the caller runs the
+    /// request and then completes or releases the key
+    Started,
+    /// A request with this key is still running
+    InProgress,
+    /// The key was first used for a different request
+    Mismatch,
+    /// The request already ran; its response should be replayed
+    Completed(StoredResponse),
+}
+
+/// Where idempotency keys live. `begin` locks a key atomically, so of two
+/// concurrent requests with the same key only one is started.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Locks `key` for the request identified by `fingerprint`, keeping it
+    /// for `ttl`. A lock older than `lock_timeout` is considered abandoned
+    /// and can be taken over by the same request.
+    async fn begin(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        ttl: Duration,
+        lock_timeout: Duration,
+    ) -> Result<Begin, DbError>;
+
+    /// Records the response of a started request.
+    async fn complete(&self, key: &str, response: &StoredResponse) -> Result<(), DbError>;
+
+    /// Unlocks a started request without a response, so a retry runs again.
+    async fn release(&self, key: &str) -> Result<(), DbError>;
+}
+
+/// The outcome for a live key, given whether it was first used for the same
+/// request and the response recorded so far.
+fn existing(same_request: bool, response: Option<StoredResponse>) -> Begin {
+    if !same_request {
+        return Begin::Mismatch;
+    }
+    match response {
+        Some(response) => Begin::Completed(response),
+        None => Begin::InProgress,
+    }
+}
+
+struct Entry {
+    fingerprint: String,
+    locked_at: Instant,
+    expires_at: Instant,
+    response: Option<StoredResponse>,
+}
+
+/// Per-process keys; only safe with a single instance, since a retry may
+/// reach another one.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+/// Expired keys are swept once the map grows past this many entries.
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryStore {
+    async fn begin(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        ttl: Duration,
+        lock_timeout: Duration,
+    ) -> Result<Begin, DbError> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("idempotency store poisoned");
+
+        if entries.len() >= MEMORY_SWEEP_THRESHOLD {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+
+        if let Some(entry) = entries.get(key) {
+            let abandoned = entry.response.is_none()
+                && entry.fingerprint == fingerprint
+                && now - entry.locked_at >= lock_timeout;
+            if entry.expires_at > now && !abandoned {
+                return Ok(existing(
+                    entry.fingerprint == fingerprint,
+                    entry.response.clone(),
+                ));
+            }
+        }
+
+        entries.insert(
+            key.to_string(),
+            Entry {
+                fingerprint: fingerprint.to_string(),
+                locked_at: now,
+                expires_at: now + ttl,
+                response: None,
+            },
+        );
+        Ok(Begin::Started)
+    }
+
+    async fn complete(&self, key: &str, response: &StoredResponse) -> Result<(), DbError> {
+        let mut entries = self.entries.lock().expect("idempotency store poisoned");
+        if let Some(entry) = entries.get_mut(key) {
+            entry.response = Some(response.clone());
+        }
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DbError> {
+        let mut entries = self.entries.lock().expect("idempotency store poisoned");
+        entries.remove(key);
+        Ok(())
+    }
+}
+
+/// Keys shared across instances in the `idempotency_keys` table.
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: DbPool,
+}
+
+#[derive(Queryable)]
+struct KeyRow {
+    fingerprint: String,
+    response_status: Option<i16>,
+    response_headers: Option<serde_json::Value>,
+    response_body: Option<Vec<u8>>,
+}
+
+impl KeyRow {
+    fn response(self) -> Option<StoredResponse> {
+        let status = u16::try_from(self.response_status?).ok()?;
+        let headers = serde_json::from_value(self.response_headers?).ok()?;
+        Some(StoredResponse {
+            status,
+            headers,
+            body: self.response_body.unwrap_or_default(),
+        })
+    }
+}
+
+impl PostgresStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Deletes expired keys.
+    pub async fn purge_expired(&self) -> Result<usize, DbError> {
+        let now: DateTime<Utc> = Utc::now();
+        with_connection(&self.pool, move |conn| {
+            diesel::delete(idempotency_keys::table.filter(idempotency_keys::expires_at.le(now)))
+                .execute(conn)
+        })
+        .await
+    }
+}
+
+/// Inserts the key, or takes over an expired or abandoned one, affecting no
+/// rows otherwise. The upsert locks the row, so concurrent calls for the
+/// same key serialize.
+const BEGIN_SQL: &str = "\
+INSERT INTO idempotency_keys AS k (key, fingerprint, locked_at, expires_at)
+VALUES ($1, $2, clock_timestamp(), clock_timestamp() + $3 * INTERVAL '1 second')
+ON CONFLICT (key) DO UPDATE
+    SET fingerprint = EXCLUDED.fingerprint,
+        locked_at = EXCLUDED.locked_at,
+        expires_at = EXCLUDED.expires_at,
+        response_status = NULL,
+        response_headers = NULL,
+        response_body = NULL
+    WHERE k.expires_at <= clock_timestamp()
+        OR (k.response_status IS NULL
+            AND k.fingerprint = EXCLUDED.fingerprint
+            AND k.locked_at <= clock_timestamp() - $4 * INTERVAL '1 second')";
+
+#[async_trait]
+impl IdempotencyStore for PostgresStore {
+    async fn begin(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        ttl: Duration,
+        lock_timeout: Duration,
+    ) -> Result<Begin, DbError> {
+        let key = key.to_owned();
+        let fingerprint = fingerprint.to_owned();
+        with_connection(&self.pool, move |conn| {
+            let started = sql_query(BEGIN_SQL)
+                .bind::<Varchar, _>(&key)
+                .bind::<Varchar, _>(&fingerprint)
+                .bind::<Double, _>(ttl.as_secs_f64())
+                .bind::<Double, _>(lock_timeout.as_secs_f64())
+                .execute(conn)?;
+            if started == 1 {
+                return Ok(Begin::Started);
+            }
+
+            let row = idempotency_keys::table
+                .find(&key)
+                .select((
+                    idempotency_keys::fingerprint,
+                    idempotency_keys::response_status,
+                    idempotency_keys::response_headers,
+                    idempotency_keys::response_body,
+                ))
+                .first::<KeyRow>(conn)
+                .optional()?;
+            // Purged in between; the client's retry will start it afresh
+            let Some(row) = row else {
+                return Ok(Begin::InProgress);
+            };
+            let same_request = row.fingerprint == fingerprint;
+            Ok(existing(same_request, row.response()))
+        })
+        .await
+    }
+
+    async fn complete(&self, key: &str, response: &StoredResponse) -> Result<(), DbError> {
+        let key = key.to_owned();
+        let status = i16::try_from(response.status).unwrap_or(i16::MAX);
+        let headers = serde_json::to_value(&response.headers).unwrap_or_default();
+        let body = response.body.clone();
+        with_connection(&self.pool, move |conn| {
+            diesel::update(idempotency_keys::table.find(key))
+                .set((
+                    idempotency_keys::response_status.eq(status),
+                    idempotency_keys::response_headers.eq(headers),
+                    idempotency_keys::response_body.eq(body),
+                ))
+                .execute(conn)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DbError> {
+        let key = key.to_owned();
+        with_connection(&self.pool, move |conn| {
+            diesel::delete(
+                idempotency_keys::table
+                    .find(key)
+                    .filter(idempotency_keys::response_status.is_null()),
+            )
+            .execute(conn)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::database::PoolSettings, test_support::TestDatabase};
+
+    const TTL: Duration = Duration::from_secs(60);
+    const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn created() -> StoredResponse {
+        StoredResponse {
+            status: 201,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: br#"{"id":1}"#.to_vec(),
+        }
+    }
+
+    async fn replays_completed_requests(store: &dyn IdempotencyStore) {
+        let begin = |fingerprint| store.begin("user:1:abc", fingerprint, TTL, LOCK_TIMEOUT);
+
+        assert_eq!(begin("a").await.unwrap(), Begin::Started);
+        assert_eq!(begin("a").await.unwrap(), Begin::InProgress);
+        assert_eq!(begin("b").await.unwrap(), Begin::Mismatch);
+
+        store.complete("user:1:abc", &created()).await.unwrap();
+        assert_eq!(begin("a").await.unwrap(), Begin::Completed(created()));
+        assert_eq!(begin("b").await.unwrap(), Begin::Mismatch);
+
+        // A released key starts over, even for another request
+        let begin = |fingerprint| store.begin("user:1:def", fingerprint, TTL, LOCK_TIMEOUT);
+        assert_eq!(begin("a").await.unwrap(), Begin::Started);
+        store.release("user:1:def").await.unwrap();
+        assert_eq!(begin("b").await.unwrap(), Begin::Started);
+
+        // Abandoned locks are taken over by the same request only
+        let begin = |fingerprint| store.begin("user:1:ghi", fingerprint, TTL, Duration::ZERO);
+        assert_eq!(begin("a").await.unwrap(), Begin::Started);
+        assert_eq!(begin("b").await.unwrap(), Begin::Mismatch);
+        assert_eq!(begin("a").await.unwrap(), Begin::Started);
+
+        // Expired keys can be reused for anything
+        let begin = |fingerprint| store.begin("user:1:jkl", fingerprint, Duration::ZERO, TTL);
+        assert_eq!(begin("a").await.unwrap(), Begin::Started);
+        store.complete("user:1:jkl", &created()).await.unwrap();
+        assert_eq!(begin("b").await.unwrap(), Begin::Started);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        replays_completed_requests(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_postgres_store() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let store = PostgresStore::new(db.pool.clone());
+
+        replays_completed_requests(&store).await;
+
+        assert_eq!(store.purge_expired().await.unwrap(), 1);
+    }
+}
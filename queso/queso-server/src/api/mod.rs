@@ -0,0 +1,18 @@
+pub mod client_ip;
+pub mod conditional;
+pub mod cors;
+pub mod error;
+pub mod extract;
+pub mod idempotency;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+pub mod validation;
+pub mod version;
+
+pub use conditional::{IfMatch, IfNoneMatch};
+pub use error::{ApiError, ProblemDetails};
+pub use extract::Path;
+pub use request_id::RequestId;
+pub use validation::{FieldError, ValidatedJson};
+pub use version::API_PREFIX;
@@ -0,0 +1,374 @@
+pub mod store;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+pub use store::{MemoryStore, PostgresStore, RateLimitStore, Take};
+
+use crate::{
+    api::{API_PREFIX, ApiError, client_ip::client_ip},
+    features::auth::{model::Keys, session},
+    shutdown::Shutdown,
+    telemetry::metrics,
+};
+
+pub static RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub static RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub static RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+pub static RATE_LIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// What a bucket is keyed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBy {
+    /// The client IP address
+    Ip,
+    /// The authenticated user, falling back to the client IP for requests
+    /// without a valid token
+    User,
+}
+
+/// `limit` requests per `window`, refilled continuously.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Identifies the policy in bucket keys and metrics
+    pub name: &'static str,
+    pub limit: u32,
+    pub window: Duration,
+    pub key: KeyBy,
+}
+
+impl Policy {
+    pub const fn per_ip(name: &'static str, limit: u32, window: Duration) -> Self {
+        Self {
+            name,
+            limit,
+            window,
+            key: KeyBy::Ip,
+        }
+    }
+
+    pub const fn per_user(name: &'static str, limit: u32, window: Duration) -> Self {
+        Self {
+            name,
+            limit,
+            window,
+            key: KeyBy::User,
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        f64::from(self.limit)
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.capacity() / self.window.as_secs_f64()
+    }
+
+    /// Seconds until `tokens` reaches `target`.
+    fn secs_until(&self, tokens: f64, target: f64) -> u64 {
+        ((target - tokens).max(0.0) / self.refill_per_sec()).ceil() as u64
+    }
+
+    /// `RateLimit-*` headers describing the bucket after `take`.
+    fn headers(&self, take: Take) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RATE_LIMIT_LIMIT.clone(), HeaderValue::from(self.limit));
+        headers.insert(
+            RATE_LIMIT_REMAINING.clone(),
+            HeaderValue::from(take.remaining.floor() as u64),
+        );
+        headers.insert(
+            RATE_LIMIT_RESET.clone(),
+            HeaderValue::from(self.secs_until(take.remaining, self.capacity())),
+        );
+        if let Ok(value) =
+            HeaderValue::from_str(&format!("{};w={}", self.limit, self.window.as_secs()))
+        {
+            headers.insert(RATE_LIMIT_POLICY.clone(), value);
+        }
+        headers
+    }
+}
+
+/// Token-bucket rate limiting with per-route policies, applied by the
+/// [`rate_limit`] middleware.
+///
+/// Routes are identified by method and matched path, e.g.
+/// `(POST, "/api/users")`. Routes without their own policy fall back to the
+/// default policy when under `/api/`; everything else, including the static
+/// UI, is not limited.
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    keys: Option<Arc<Keys>>,
+    trusted_proxy_hops: usize,
+    routes: Vec<(Method, String, Policy)>,
+    default: Option<Policy>,
+}
+
+impl RateLimiter {
+    pub fn new(store: impl RateLimitStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            keys: None,
+            trusted_proxy_hops: 0,
+            routes: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Keys used to identify users for [`KeyBy::User`] policies; without
+    /// them every request is keyed by IP.
+    pub fn with_keys(mut self, keys: Arc<Keys>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Number of proxies in front of the server whose `X-Forwarded-For`
+    /// entries are trusted. With `0` the peer address is used.
+    pub fn with_trusted_proxy_hops(mut self, hops: usize) -> Self {
+        self.trusted_proxy_hops = hops;
+        self
+    }
+
+    pub fn with_default(mut self, policy: Policy) -> Self {
+        self.default = Some(policy);
+        self
+    }
+
+    pub fn with_route(mut self, method: Method, path: impl Into<String>, policy: Policy) -> Self {
+        self.routes.push((method, path.into(), policy));
+        self
+    }
+
+    fn policy_for(&self, method: &Method, path: &str) -> Option<Policy> {
+        self.routes
+            .iter()
+            .find(|(route_method, route_path, _)| route_method == method && route_path == path)
+            .map(|(_, _, policy)| *policy)
+            .or_else(|| self.default.filter(|_| path.starts_with(API_PREFIX)))
+    }
+
+    fn bucket_key(&self, policy: &Policy, req: &Request) -> String {
+        let user_id = match (policy.key, &self.keys) {
+            (KeyBy::User, Some(keys)) => session::credential(req.headers())
+                .and_then(|credential| keys.verify(credential.token()).ok())
+                .map(|claims| claims.user_id),
+            _ => None,
+        };
+
+        match user_id {
+            Some(user_id) => format!("{}:user:{}", policy.name, user_id),
+            None => match client_ip(req, self.trusted_proxy_hops) {
+                Some(ip) => format!("{}:ip:{}", policy.name, ip),
+                None => format!("{}:ip:unknown", policy.name),
+            },
+        }
+    }
+}
+
+/// Takes a token for the request's policy, answering `429 Too Many Requests`
+/// once the bucket is empty. Allowed responses carry the `RateLimit-*`
+/// headers too. If the store fails the request is let through.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let policy = req
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| limiter.policy_for(req.method(), path.as_str()));
+    let Some(policy) = policy else {
+        return next.run(req).await;
+    };
+
+    let key = limiter.bucket_key(&policy, &req);
+    let take = match limiter
+        .store
+        .take(&key, policy.capacity(), policy.refill_per_sec())
+        .await
+    {
+        Ok(take) => take,
+        Err(e) => {
+            tracing::warn!(policy = policy.name, error = %e, "Rate limit store failed, allowing request");
+            return next.run(req).await;
+        }
+    };
+
+    let headers = policy.headers(take);
+    let mut response = if take.allowed {
+        next.run(req).await
+    } else {
+        metrics::record_rate_limited(policy.name);
+        ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            "Too many requests, please retry later",
+        )
+        .with_header(
+            header::RETRY_AFTER,
+            HeaderValue::from(policy.secs_until(take.remaining, 1.0).max(1)),
+        )
+        .into_response()
+    };
+    response.headers_mut().extend(headers);
+    response
+}
+
+/// Buckets idle for this long are deleted; every policy refills well within it.
+const PURGE_IDLE_AFTER: Duration = Duration::from_secs(60 * 60);
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Periodically deletes idle buckets from the Postgres store until shutdown.
+pub fn spawn_purge_worker(store: PostgresStore, shutdown: &Shutdown) {
+    let worker = shutdown.clone();
+    shutdown.spawn(async move {
+        loop {
+            tokio::select! {
+                _ = worker.cancelled() => break,
+                _ = tokio::time::sleep(PURGE_INTERVAL) => {}
+            }
+
+            match store.purge_idle(PURGE_IDLE_AFTER).await {
+                Ok(purged) => tracing::debug!(purged, "Purged idle rate limit buckets"),
+                Err(e) => tracing::warn!(error = %e, "Failed to purge rate limit buckets"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::auth::model::Claims;
+    use axum::{
+        Router,
+        body::Body,
+        extract::ConnectInfo,
+        middleware,
+        routing::{get, post},
+    };
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    const SIGNUP: Policy = Policy::per_ip("signup", 2, Duration::from_secs(60));
+    const DEFAULT: Policy = Policy::per_user("default", 100, Duration::from_secs(60));
+
+    fn app(limiter: RateLimiter) -> Router {
+        let users = Router::new()
+            .route("/", post(|| async { "created" }))
+            .route("/{id}", get(|| async { "user" }));
+
+        Router::new()
+            .nest("/api/users", users)
+            .route("/health/live", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(limiter),
+                rate_limit,
+            ))
+    }
+
+    fn request(method: Method, uri: &str, ip: &str) -> Request {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(ip.parse().unwrap(), 40000)));
+        req
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_bucket_returns_429_with_headers() {
+        let app = app(RateLimiter::new(MemoryStore::new()).with_route(
+            Method::POST,
+            "/api/users",
+            SIGNUP,
+        ));
+
+        for remaining in ["1", "0"] {
+            let response = app
+                .clone()
+                .oneshot(request(Method::POST, "/api/users", "198.51.100.1"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[&RATE_LIMIT_LIMIT], "2");
+            assert_eq!(response.headers()[&RATE_LIMIT_REMAINING], remaining);
+            assert_eq!(response.headers()[&RATE_LIMIT_POLICY], "2;w=60");
+        }
+
+        let response = app
+            .clone()
+            .oneshot(request(Method::POST, "/api/users", "198.51.100.1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[&RATE_LIMIT_REMAINING], "0");
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "rate_limited");
+
+        // Another client has its own bucket
+        let response = app
+            .oneshot(request(Method::POST, "/api/users", "198.51.100.2"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_policies_apply_per_route() {
+        let app = app(RateLimiter::new(MemoryStore::new())
+            .with_route(Method::POST, "/api/users", SIGNUP)
+            .with_default(DEFAULT));
+
+        let response = app
+            .clone()
+            .oneshot(request(Method::GET, "/api/users/1", "198.51.100.1"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[&RATE_LIMIT_LIMIT], "100");
+
+        let response = app
+            .oneshot(request(Method::GET, "/health/live", "198.51.100.1"))
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(&RATE_LIMIT_LIMIT));
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_users_get_their_own_bucket() {
+        let keys = Arc::new(Keys::new(b"rate-limit-test-secret"));
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &Claims::new(42),
+            &keys.encoding,
+        )
+        .unwrap();
+        let limiter = RateLimiter::new(MemoryStore::new()).with_keys(keys);
+
+        let mut req = request(Method::GET, "/api/users/1", "198.51.100.1");
+        assert_eq!(
+            limiter.bucket_key(&DEFAULT, &req),
+            "default:ip:198.51.100.1"
+        );
+
+        req.headers_mut().insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        assert_eq!(limiter.bucket_key(&DEFAULT, &req), "default:user:42");
+        assert_eq!(limiter.bucket_key(&SIGNUP, &req), "signup:ip:198.51.100.1");
+    }
+}
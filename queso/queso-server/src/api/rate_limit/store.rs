@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Bool, Double, Varchar},
+};
+
+use crate::{
+    config::database::{DbError, DbPool, with_connection},
+    schema::rate_limit_buckets,
+};
+
+/// Result of taking a token from a bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Take {
+    pub allowed: bool,
+    /// Tokens left after this request
+    pub remaining: f64,
+}
+
+/// Where token buckets live. Implementations refill and take atomically.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn take(&self, key: &str, capacity: f64, refill_per_sec: f64) -> Result<Take, DbError>;
+}
+
+/// Refills `tokens` for `elapsed` and takes one token if available.
+fn refill_and_take(tokens: f64, elapsed: Duration, capacity: f64, refill_per_sec: f64) -> Take {
+    let available = (tokens + elapsed.as_secs_f64() * refill_per_sec).min(capacity);
+    if available >= 1.0 {
+        Take {
+            allowed: true,
+            remaining: available - 1.0,
+        }
+    } else {
+        Take {
+            allowed: false,
+            remaining: available,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// When the bucket is full again and can be forgotten
+    full_at: Instant,
+}
+
+/// Per-process buckets; fine for a single instance.
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// Full buckets are swept once the map grows past this many keys.
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, capacity: f64, refill_per_sec: f64) -> Result<Take, DbError> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit store poisoned");
+
+        if buckets.len() >= MEMORY_SWEEP_THRESHOLD {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let take = match buckets.get(key) {
+            Some(bucket) => refill_and_take(
+                bucket.tokens,
+                now - bucket.updated_at,
+                capacity,
+                refill_per_sec,
+            ),
+            None => refill_and_take(capacity, Duration::ZERO, capacity, refill_per_sec),
+        };
+
+        let until_full = Duration::from_secs_f64((capacity - take.remaining) / refill_per_sec);
+        buckets.insert(
+            key.to_string(),
+            Bucket {
+                tokens: take.remaining,
+                updated_at: now,
+                full_at: now + until_full,
+            },
+        );
+
+        Ok(take)
+    }
+}
+
+/// Buckets shared across instances through the `rate_limit_take` function.
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: DbPool,
+}
+
+#[derive(QueryableByName)]
+struct TakeRow {
+    #[diesel(sql_type = Bool)]
+    allowed: bool,
+    #[diesel(sql_type = Double)]
+    remaining: f64,
+}
+
+impl PostgresStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Deletes buckets untouched for `idle`; they would have refilled anyway.
+    pub async fn purge_idle(&self, idle: Duration) -> Result<usize, DbError> {
+        let cutoff =
+            chrono::Utc::now() - chrono::Duration::from_std(idle).unwrap_or(chrono::Duration::MAX);
+        with_connection(&self.pool, move |conn| {
+            diesel::delete(
+                rate_limit_buckets::table.filter(rate_limit_buckets::updated_at.lt(cutoff)),
+            )
+            .execute(conn)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn take(&self, key: &str, capacity: f64, refill_per_sec: f64) -> Result<Take, DbError> {
+        let key = key.to_owned();
+        let row = with_connection(&self.pool, move |conn| {
+            sql_query("SELECT allowed, remaining FROM rate_limit_take($1, $2, $3)")
+                .bind::<Varchar, _>(key)
+                .bind::<Double, _>(capacity)
+                .bind::<Double, _>(refill_per_sec)
+                .get_result::<TakeRow>(conn)
+        })
+        .await?;
+
+        Ok(Take {
+            allowed: row.allowed,
+            remaining: row.remaining,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::database::PoolSettings, test_support::TestDatabase};
+
+    #[test]
+    fn test_refill_and_take() {
+        let take = refill_and_take(0.5, Duration::from_millis(500), 5.0, 1.0);
+        assert!(take.allowed);
+        assert_eq!(take.remaining, 0.0);
+
+        let take = refill_and_take(0.0, Duration::from_millis(500), 5.0, 1.0);
+        assert!(!take.allowed);
+        assert_eq!(take.remaining, 0.5);
+
+        // Never refills past capacity
+        let take = refill_and_take(4.0, Duration::from_secs(60), 5.0, 1.0);
+        assert_eq!(take.remaining, 4.0);
+    }
+
+    async fn exhausts_after_capacity(store: &dyn RateLimitStore) {
+        for expected in [2.0, 1.0, 0.0] {
+            let take = store.take("signup:10.0.0.1", 3.0, 0.01).await.unwrap();
+            assert!(take.allowed);
+            assert!((take.remaining - expected).abs() < 0.01, "{:?}", take);
+        }
+
+        let take = store.take("signup:10.0.0.1", 3.0, 0.01).await.unwrap();
+        assert!(!take.allowed);
+
+        // Other keys have their own bucket
+        let take = store.take("signup:10.0.0.2", 3.0, 0.01).await.unwrap();
+        assert!(take.allowed);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        exhausts_after_capacity(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_postgres_store() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let store = PostgresStore::new(db.pool.clone());
+
+        exhausts_after_capacity(&store).await;
+
+        assert_eq!(store.purge_idle(Duration::ZERO).await.unwrap(), 2);
+    }
+}
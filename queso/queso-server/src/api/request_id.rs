@@ -0,0 +1,67 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: RequestId;
+}
+
+/// Identifier attached to every request, echoed in the `X-Request-Id`
+/// response header and in error bodies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Reuses a client-supplied id when it is short and printable, otherwise
+    /// generates a fresh one.
+    fn from_header(value: Option<&HeaderValue>) -> Self {
+        value
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| !id.is_empty() && id.len() <= 128)
+            .filter(|id| id.chars().all(|c| c.is_ascii_graphic()))
+            .map(|id| Self(id.to_string()))
+            .unwrap_or_else(Self::generate)
+    }
+
+    fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// The id of the request currently being handled, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT_REQUEST_ID.try_with(Clone::clone).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Assigns a `RequestId` to the request, makes it available to handlers and
+/// error responses, and echoes it back in the response headers.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = RequestId::from_header(req.headers().get(&REQUEST_ID_HEADER));
+    req.extensions_mut().insert(id.clone());
+
+    let mut response = CURRENT_REQUEST_ID.scope(id.clone(), next.run(req)).await;
+
+    if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), value);
+    }
+
+    response
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use rand::RngCore;
+
+use crate::config::settings::SecuritySettings;
+
+/// Replaced with the request's nonce in the configured policy.
+pub const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// Swagger UI ships its own inline scripts and styles, so it is left out of
+/// the CSP; every other header still applies.
+const CSP_EXEMPT_PREFIX: &str = "/swagger-ui";
+
+static PERMISSIONS_POLICY: HeaderName = HeaderName::from_static("permissions-policy");
+
+const HSTS: &str = "max-age=31536000; includeSubDomains";
+const PERMISSIONS: &str = "camera=(), microphone=(), geolocation=(), payment=(), usb=()";
+
+/// Random value allowed by the CSP for this request's inline scripts.
+/// Available to handlers as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        rand::rng().fill_bytes(&mut bytes);
+        Self(STANDARD.encode(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    #[cfg(test)]
+    pub(crate) fn for_tests(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+/// Sets HSTS, CSP, `X-Content-Type-Options`, `Referrer-Policy`,
+/// `Permissions-Policy` and `X-Frame-Options` on every response. Headers a
+/// handler already set are left alone.
+pub async fn security_headers(
+    State(settings): State<Arc<SecuritySettings>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let nonce = CspNonce::generate();
+    let csp_exempt = req.uri().path().starts_with(CSP_EXEMPT_PREFIX);
+    req.extensions_mut().insert(nonce.clone());
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+
+    let mut set_default = |name: HeaderName, value: HeaderValue| {
+        headers.entry(name).or_insert(value);
+    };
+    set_default(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    set_default(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("strict-origin-when-cross-origin"),
+    );
+    set_default(
+        PERMISSIONS_POLICY.clone(),
+        HeaderValue::from_static(PERMISSIONS),
+    );
+    set_default(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    if settings.hsts {
+        set_default(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static(HSTS),
+        );
+    }
+    if !csp_exempt {
+        let policy = settings
+            .content_security_policy
+            .replace(NONCE_PLACEHOLDER, nonce.as_str());
+        // Validated at startup; the nonce is base64
+        if let Ok(value) = HeaderValue::from_str(&policy) {
+            set_default(header::CONTENT_SECURITY_POLICY, value);
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn app(hsts: bool) -> Router {
+        let settings = SecuritySettings {
+            hsts,
+            content_security_policy: "default-src 'self'; script-src 'self' 'nonce-{nonce}'"
+                .to_string(),
+        };
+        Router::new()
+            .route(
+                "/",
+                get(|Extension(nonce): Extension<CspNonce>| async move {
+                    nonce.as_str().to_string()
+                }),
+            )
+            .route("/swagger-ui/index.html", get(|| async { "docs" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(settings),
+                security_headers,
+            ))
+    }
+
+    async fn get_page(app: Router, uri: &str) -> (axum::http::HeaderMap, String) {
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (headers, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_headers_are_set_with_a_fresh_nonce() {
+        let (headers, nonce) = get_page(app(true), "/").await;
+
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(
+            headers[header::REFERRER_POLICY],
+            "strict-origin-when-cross-origin"
+        );
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert!(headers.contains_key(&PERMISSIONS_POLICY));
+        assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], HSTS);
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            format!("default-src 'self'; script-src 'self' 'nonce-{}'", nonce).as_str()
+        );
+
+        let (_, other_nonce) = get_page(app(true), "/").await;
+        assert_ne!(nonce, other_nonce);
+    }
+
+    #[tokio::test]
+    async fn test_hsts_is_optional_and_swagger_ui_skips_csp() {
+        let (headers, _) = get_page(app(false), "/swagger-ui/index.html").await;
+
+        assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+        assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+}
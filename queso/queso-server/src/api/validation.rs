@@ -0,0 +1,143 @@
+use axum::{
+    Json,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use super::error::ApiError;
+
+/// JSON extractor that runs the payload's `Validate` rules before the handler
+/// sees it, rejecting invalid payloads with a `422` listing every failing field.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        value.validate()?;
+        Ok(Self(value))
+    }
+}
+
+/// A single failing field and the reason it was rejected.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| FieldError {
+                    field: field.to_string(),
+                    reason: reason(error),
+                })
+            })
+            .collect();
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+            "Request validation failed",
+        )
+        .with_errors(fields)
+    }
+}
+
+fn reason(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    match error.code.as_ref() {
+        "email" => "must be a valid email address".to_string(),
+        "length" => "has an invalid length".to_string(),
+        "required" => "is required".to_string(),
+        code => format!("is invalid ({})", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::post};
+    use serde::Deserialize;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[derive(Deserialize, Validate)]
+    struct Probe {
+        #[validate(email)]
+        email: String,
+        #[validate(length(min = 3, message = "must be at least 3 characters"))]
+        name: String,
+    }
+
+    async fn probe(ValidatedJson(probe): ValidatedJson<Probe>) -> String {
+        format!("{} {}", probe.name, probe.email)
+    }
+
+    async fn send(body: &str) -> (StatusCode, serde_json::Value) {
+        let app = Router::new().route("/", post(probe));
+        let response = app
+            .oneshot(
+                Request::post("/")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_valid_payload_reaches_handler() {
+        let (status, _) = send(r#"{"email":"bob@example.com","name":"bob"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_payload_lists_every_field() {
+        let (status, body) = send(r#"{"email":"not-an-email","name":"b"}"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "validation_failed");
+        assert_eq!(
+            body["errors"],
+            json!([
+                { "field": "email", "reason": "must be a valid email address" },
+                { "field": "name", "reason": "must be at least 3 characters" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_rejected_before_validation() {
+        let (status, body) = send(r#"{"email":"#).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_body");
+        assert!(body.get("errors").is_none());
+    }
+}
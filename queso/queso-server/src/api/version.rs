@@ -0,0 +1,91 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+
+/// Every API route, versioned or not, lives under this prefix.
+pub const API_PREFIX: &str = "/api/";
+/// Current API version; breaking changes go into a new prefix.
+pub const V1_PREFIX: &str = "/api/v1";
+/// Unversioned aliases of v1, kept until [`LEGACY_SUNSET`].
+pub const LEGACY_PREFIX: &str = "/api";
+
+/// When the unversioned paths were deprecated (2026-10-19), as a Unix time.
+const LEGACY_DEPRECATED_AT: u64 = 1_792_368_000;
+/// When the unversioned paths will be removed.
+pub const LEGACY_SUNSET: &str = "Mon, 19 Apr 2027 00:00:00 GMT";
+
+pub static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+pub static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Marks responses from the unversioned aliases as deprecated (RFC 9745),
+/// announces their removal (RFC 8594) and links the `/api/v1` equivalent.
+pub async fn legacy_alias(req: Request, next: Next) -> Response {
+    let successor = successor_path(req.uri().path());
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+
+    headers.insert(
+        DEPRECATION.clone(),
+        HeaderValue::from_str(&format!("@{}", LEGACY_DEPRECATED_AT))
+            .expect("Unix time is a valid header value"),
+    );
+    headers.insert(SUNSET.clone(), HeaderValue::from_static(LEGACY_SUNSET));
+    if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.append(header::LINK, link);
+    }
+
+    response
+}
+
+fn successor_path(path: &str) -> String {
+    let rest = path.strip_prefix(LEGACY_PREFIX).unwrap_or(path);
+    format!("{}{}", V1_PREFIX, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        let v1 = Router::new().route("/users/{id}", get(|| async { "user" }));
+        Router::new()
+            .nest(V1_PREFIX, v1.clone())
+            .nest(LEGACY_PREFIX, v1.layer(middleware::from_fn(legacy_alias)))
+    }
+
+    async fn get_path(uri: &str) -> Response {
+        app()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_legacy_paths_are_deprecated_aliases() {
+        let response = get_path("/api/users/7").await;
+        let headers = response.headers();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(headers[&DEPRECATION], "@1792368000");
+        assert_eq!(headers[&SUNSET], LEGACY_SUNSET);
+        assert_eq!(
+            headers[header::LINK],
+            "</api/v1/users/7>; rel=\"successor-version\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_versioned_paths_are_not_deprecated() {
+        let response = get_path("/api/v1/users/7").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(&DEPRECATION));
+        assert!(!response.headers().contains_key(&SUNSET));
+    }
+}
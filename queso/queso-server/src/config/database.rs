@@ -1,15 +1,86 @@
 use diesel::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::telemetry::metrics::PoolMetrics;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
-pub fn establish_connection_pool() -> DbPool {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+#[derive(Debug, Error)]
+pub enum DbError {
+    /// No connection could be obtained: the pool is exhausted or the database
+    /// is unreachable. Callers should treat this as transient.
+    #[error("Database unavailable: {0}")]
+    Unavailable(String),
+    #[error("Query error: {0}")]
+    Query(#[from] DieselError),
+}
+
+impl From<r2d2::PoolError> for DbError {
+    fn from(error: r2d2::PoolError) -> Self {
+        DbError::Unavailable(error.to_string())
+    }
+}
+
+impl DbError {
+    /// Folds connection-level query failures (e.g. the server went away
+    /// mid-query) into `Unavailable`.
+    fn classify(self) -> Self {
+        match self {
+            DbError::Query(DieselError::DatabaseError(
+                DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::UnableToSendCommand,
+                info,
+            )) => DbError::Unavailable(info.message().to_string()),
+            error => error,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub max_size: u32,
+    pub min_idle: Option<u32>,
+    /// How long a request waits for a free connection before failing with
+    /// `DbError::Unavailable`
+    pub connection_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_idle: None,
+            connection_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+impl PoolSettings {
+    fn builder(&self) -> r2d2::Builder<ConnectionManager<PgConnection>> {
+        r2d2::Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(self.min_idle)
+            .connection_timeout(self.connection_timeout)
+            .idle_timeout(self.idle_timeout)
+            .max_lifetime(self.max_lifetime)
+            .test_on_check_out(true)
+            .event_handler(Box::new(PoolMetrics))
+    }
+}
+
+pub fn establish_connection_pool(database_url: &str, settings: &PoolSettings) -> DbPool {
     let manager = ConnectionManager::<PgConnection>::new(database_url);
-    r2d2::Pool::builder()
+    settings
+        .builder()
         .build(manager)
         .expect("Failed to create pool")
 }
@@ -20,3 +91,115 @@ pub fn run_migrations(pool: &DbPool) {
         .expect("Failed to run migrations");
     tracing::info!("Database migrations completed successfully");
 }
+
+/// Runs `query` on a pooled connection on Tokio's blocking thread pool, so
+/// synchronous Diesel calls (and waiting for a free connection) never stall
+/// the async worker threads.
+pub async fn with_connection<T, F>(pool: &DbPool, query: F) -> Result<T, DbError>
+where
+    F: FnOnce(&mut PgConnection) -> Result<T, DieselError> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    let task = tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        query(&mut conn).map_err(DbError::from)
+    });
+
+    match task.await {
+        Ok(result) => result.map_err(DbError::classify),
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(DbError::Unavailable(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        server,
+        shutdown::Shutdown,
+        test_support::{self, TestDatabase},
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use diesel::{Connection, RunQueryDsl, sql_query};
+    use std::time::{Duration, Instant};
+    use tower::ServiceExt;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[ignore = "needs Docker"]
+    async fn test_slow_queries_do_not_block_the_server() {
+        let settings = PoolSettings {
+            max_size: 20,
+            ..PoolSettings::default()
+        };
+        let db = TestDatabase::start(settings).await;
+        let app = server::app(&test_support::settings(), db.pool.clone(), &Shutdown::new());
+        let get = |uri: &str| {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(request)
+        };
+
+        // Hold a lock on `users` so every user query waits for it
+        let (locked_tx, locked_rx) = tokio::sync::oneshot::channel();
+        let pool = db.pool.clone();
+        let lock = tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get().unwrap();
+            conn.transaction(|conn| {
+                sql_query("LOCK TABLE users IN ACCESS EXCLUSIVE MODE").execute(conn)?;
+                locked_tx.send(()).unwrap();
+                std::thread::sleep(Duration::from_secs(2));
+                Ok::<_, DieselError>(())
+            })
+        });
+        locked_rx.await.unwrap();
+
+        // Far more slow requests than there are worker threads
+        let started = Instant::now();
+        let slow: Vec<_> = (0..16)
+            .map(|_| tokio::spawn(get("/api/v1/users")))
+            .collect();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // While they are all waiting on the database, the server still
+        // answers other requests promptly
+        for _ in 0..10 {
+            let probe = Instant::now();
+            let response = get("/health/live").await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(
+                probe.elapsed() < Duration::from_millis(250),
+                "/health/live took {:?} while queries were running",
+                probe.elapsed()
+            );
+        }
+
+        lock.await.unwrap().unwrap();
+        for request in slow {
+            assert_eq!(request.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_database_is_unavailable() {
+        let settings = PoolSettings {
+            max_size: 1,
+            connection_timeout: Duration::from_millis(200),
+            ..PoolSettings::default()
+        };
+        let manager = ConnectionManager::<PgConnection>::new("postgres://queso@127.0.0.1:1/queso");
+        let pool = settings.builder().build_unchecked(manager);
+
+        let result = with_connection(&pool, |conn| sql_query("SELECT 1").execute(conn)).await;
+
+        assert!(
+            matches!(result, Err(DbError::Unavailable(_))),
+            "{:?}",
+            result
+        );
+    }
+}
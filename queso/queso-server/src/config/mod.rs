@@ -1 +1,6 @@
 pub mod database;
+pub mod root;
+pub mod settings;
+
+pub use root::resolve_root;
+pub use settings::Settings;
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+/// Entries whose presence marks a directory as the deployment root.
+const ROOT_MARKERS: [&str; 3] = [".env", "config", "dist"];
+
+/// Finds the directory `.env`, `config/` and `dist/` are resolved against.
+///
+/// In order:
+/// 1. `QUESO_ROOT`, when set
+/// 2. the working directory, the binary's directory or its parent, whichever
+///    first contains one of the root markers
+/// 3. in debug builds only, the enclosing git repository, so `cargo run`
+///    works from anywhere in a checkout
+/// 4. the working directory
+pub fn resolve_root() -> PathBuf {
+    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+    if let Some(root) = std::env::var_os("QUESO_ROOT").filter(|root| !root.is_empty()) {
+        return PathBuf::from(root);
+    }
+
+    let mut candidates = vec![current_dir.clone()];
+    if let Ok(exe) = std::env::current_exe() {
+        candidates.extend(exe.ancestors().skip(1).take(2).map(Path::to_path_buf));
+    }
+
+    find_marked(&candidates)
+        .or_else(|| git_root(&current_dir))
+        .unwrap_or(current_dir)
+}
+
+fn find_marked(candidates: &[PathBuf]) -> Option<PathBuf> {
+    candidates
+        .iter()
+        .find(|dir| ROOT_MARKERS.iter().any(|marker| dir.join(marker).exists()))
+        .cloned()
+}
+
+#[cfg(debug_assertions)]
+fn git_root(start: &Path) -> Option<PathBuf> {
+    let repo = git2::Repository::discover(start).ok()?;
+    repo.workdir().map(Path::to_path_buf)
+}
+
+#[cfg(not(debug_assertions))]
+fn git_root(_start: &Path) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_marked_candidate_wins() {
+        let base = std::env::temp_dir().join(format!("queso-root-{}", std::process::id()));
+        let unmarked = base.join("bin");
+        let marked = base.join("app");
+        std::fs::create_dir_all(&unmarked).unwrap();
+        std::fs::create_dir_all(marked.join("dist")).unwrap();
+
+        let found = find_marked(&[unmarked.clone(), marked.clone()]);
+        let missing = find_marked(&[unmarked]);
+        std::fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(found, Some(marked));
+        assert_eq!(missing, None);
+    }
+}
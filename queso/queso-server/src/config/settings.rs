@@ -0,0 +1,1129 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use thiserror::Error;
+use toml::{Table, Value};
+use tracing_subscriber::filter::{Directive, LevelFilter};
+
+use super::database::PoolSettings;
+use crate::api::cors::OriginPattern;
+
+/// Minimum JWT secret length accepted by the `prod` profile.
+const MIN_PROD_JWT_SECRET_LEN: usize = 32;
+
+/// Selects which `queso.<profile>.toml` overlay is applied and a few
+/// profile-specific defaults. Read from `QUESO_PROFILE`, defaulting to `dev`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Dev,
+    Test,
+    Prod,
+}
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Test => "test",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Ok(Profile::Dev),
+            "test" => Ok(Profile::Test),
+            "prod" | "production" => Ok(Profile::Prod),
+            other => Err(format!(
+                "unknown profile {:?}, expected dev, test or prod",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Server configuration, loaded and validated once at startup.
+///
+/// Values are layered, later sources winning:
+/// 1. built-in defaults
+/// 2. `<config dir>/queso.toml`
+/// 3. `<config dir>/queso.<profile>.toml`
+/// 4. environment variables (the names used in `.env.example`)
+///
+/// The config dir is `QUESO_CONFIG_DIR`, or `config` under the root found by
+/// [`resolve_root`](super::root::resolve_root); both files are optional.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub profile: Profile,
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub auth: AuthSettings,
+    pub google: GoogleSettings,
+    pub telemetry: TelemetrySettings,
+    pub logging: LoggingSettings,
+    pub rate_limit: RateLimitSettings,
+    pub idempotency: IdempotencySettings,
+    pub users: UserSettings,
+    pub cors: CorsSettings,
+    pub security: SecuritySettings,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    /// Built UI served as the fallback for non-API routes
+    pub static_dir: PathBuf,
+    /// How long the server keeps accepting connections after SIGTERM/SIGINT
+    /// while readiness fails, so load balancers stop routing to it first
+    pub pre_stop_delay: Duration,
+    /// How long in-flight requests and background tasks get to finish after
+    /// the listener closes
+    pub shutdown_timeout: Duration,
+}
+
+impl ServerSettings {
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseSettings {
+    pub url: String,
+    pub pool: PoolSettings,
+}
+
+#[derive(Clone)]
+pub struct AuthSettings {
+    pub jwt_secret: String,
+    /// Hide whether an account exists from login and sign-up responses
+    pub hardened_mode: bool,
+    pub session: SessionSettings,
+    /// Users allowed to call the admin endpoints
+    pub admin_user_ids: Vec<i32>,
+}
+
+impl fmt::Debug for AuthSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthSettings")
+            .field("jwt_secret", &"<redacted>")
+            .field("hardened_mode", &self.hardened_mode)
+            .field("session", &self.session)
+            .field("admin_user_ids", &self.admin_user_ids)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+        }
+    }
+}
+
+impl FromStr for SameSite {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            other => Err(format!(
+                "unknown SameSite value {:?}, expected strict or lax",
+                other
+            )),
+        }
+    }
+}
+
+/// How logins hand the token to browsers.
+#[derive(Debug, Clone)]
+pub struct SessionSettings {
+    /// Set the token as an `HttpOnly` cookie instead of returning it in the
+    /// response body
+    pub cookies: bool,
+    /// Mark cookies `Secure`; only disable for plain-HTTP local development
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+#[derive(Clone)]
+pub struct GoogleSettings {
+    pub client_id: String,
+    pub client_secret: String,
+    pub auth_url: String,
+    pub token_url: String,
+    pub redirect_url: String,
+    pub userinfo_url: String,
+}
+
+impl fmt::Debug for GoogleSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GoogleSettings")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("auth_url", &self.auth_url)
+            .field("token_url", &self.token_url)
+            .field("redirect_url", &self.redirect_url)
+            .field("userinfo_url", &self.userinfo_url)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP collector base URL; traces are only exported when set
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "unknown log format {:?}, expected text or json",
+                other
+            )),
+        }
+    }
+}
+
+/// Used when `RUST_LOG` is unset; `RUST_LOG` replaces both fields entirely.
+#[derive(Debug, Clone)]
+pub struct LoggingSettings {
+    pub format: LogFormat,
+    pub level: LevelFilter,
+    /// Per-target overrides of `level`, e.g. `tower_http=debug`
+    pub overrides: Vec<Directive>,
+}
+
+/// Where rate limit buckets are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitBackend {
+    /// Per process; each instance enforces its own limits
+    Memory,
+    /// Shared by every instance through the database
+    Postgres,
+}
+
+impl FromStr for RateLimitBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "memory" => Ok(RateLimitBackend::Memory),
+            "postgres" => Ok(RateLimitBackend::Postgres),
+            other => Err(format!(
+                "unknown rate limit store {:?}, expected memory or postgres",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub store: RateLimitBackend,
+    /// Number of proxies in front of the server (1 for the ALB, the prod
+    /// default) whose `X-Forwarded-For` entries are trusted when finding the
+    /// client IP
+    pub trusted_proxy_hops: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdempotencySettings {
+    pub enabled: bool,
+    /// How long a response is kept for replay to retries with the same
+    /// `Idempotency-Key`
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserSettings {
+    /// How long deleted users can be restored before they are purged
+    pub deleted_retention: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsSettings {
+    /// Origins allowed to call the API from a browser; empty means
+    /// same-origin only
+    pub allowed_origins: Vec<OriginPattern>,
+    /// Let browsers send cookies with cross-origin requests
+    pub allow_credentials: bool,
+    /// How long browsers may cache preflight responses
+    pub max_age: Duration,
+}
+
+/// Allows the app's own scripts plus the analytics and error reporting
+/// endpoints the UI talks to. `{nonce}` is replaced per request.
+const DEFAULT_CSP: &str = "default-src 'self'; \
+    script-src 'self' 'nonce-{nonce}' https://*.posthog.com; \
+    style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data: https:; \
+    font-src 'self' data:; \
+    connect-src 'self' https://*.posthog.com https://*.sentry.io; \
+    object-src 'none'; \
+    base-uri 'self'; \
+    form-action 'self'; \
+    frame-ancestors 'none'";
+
+#[derive(Debug, Clone)]
+pub struct SecuritySettings {
+    /// Send `Strict-Transport-Security`; only enable behind HTTPS
+    pub hsts: bool,
+    /// `Content-Security-Policy`, with `{nonce}` standing in for the
+    /// per-request nonce
+    pub content_security_policy: String,
+}
+
+/// A single missing or invalid setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingError {
+    /// Dotted key as used in the TOML files, e.g. `database.url`
+    pub key: &'static str,
+    /// Environment variable overriding the key
+    pub env: &'static str,
+    pub problem: String,
+}
+
+impl fmt::Display for SettingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.key, self.env, self.problem)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Invalid configuration:{}", report(.0))]
+    Invalid(Vec<SettingError>),
+}
+
+fn report(errors: &[SettingError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("\n  - {}", error))
+        .collect()
+}
+
+impl Settings {
+    /// Loads settings from the config files and the process environment.
+    /// Relative paths are resolved against `root`.
+    pub fn load(root: &Path) -> Result<Self, ConfigError> {
+        let env: HashMap<String, String> = std::env::vars().collect();
+        let config_dir = root.join(
+            env.get("QUESO_CONFIG_DIR")
+                .map(String::as_str)
+                .unwrap_or("config"),
+        );
+
+        let profile = env
+            .get("QUESO_PROFILE")
+            .map(|value| value.parse::<Profile>())
+            .transpose()
+            .map_err(|problem| {
+                ConfigError::Invalid(vec![SettingError {
+                    key: "profile",
+                    env: "QUESO_PROFILE",
+                    problem,
+                }])
+            })?
+            .unwrap_or(Profile::Dev);
+
+        let layers = [
+            config_dir.join("queso.toml"),
+            config_dir.join(format!("queso.{}.toml", profile)),
+        ]
+        .iter()
+        .filter_map(|path| read_layer(path).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let mut settings = Self::from_sources(profile, &layers, &env)?;
+        settings.server.static_dir = root.join(&settings.server.static_dir);
+        Ok(settings)
+    }
+
+    /// Builds settings from already-read TOML layers (lowest precedence
+    /// first) and environment variables, reporting every problem at once.
+    pub fn from_sources(
+        profile: Profile,
+        layers: &[Table],
+        env: &HashMap<String, String>,
+    ) -> Result<Self, ConfigError> {
+        let mut loader = Loader {
+            layers,
+            env,
+            errors: Vec::new(),
+        };
+        let pool_defaults = PoolSettings::default();
+
+        let server = ServerSettings {
+            host: loader.optional("server.host", "SERVER_HOST", "127.0.0.1".to_string()),
+            port: loader.optional("server.port", "API_PORT", 3000),
+            static_dir: loader.optional("server.static_dir", "STATIC_DIR", PathBuf::from("dist")),
+            pre_stop_delay: loader.optional_secs(
+                "server.pre_stop_delay_secs",
+                "SHUTDOWN_PRE_STOP_DELAY_SECS",
+                if profile == Profile::Prod {
+                    Duration::from_secs(5)
+                } else {
+                    Duration::ZERO
+                },
+            ),
+            shutdown_timeout: loader.optional_secs(
+                "server.shutdown_timeout_secs",
+                "SHUTDOWN_TIMEOUT_SECS",
+                Duration::from_secs(20),
+            ),
+        };
+
+        let pool = PoolSettings {
+            max_size: loader.optional(
+                "database.pool.max_size",
+                "DATABASE_POOL_MAX_SIZE",
+                pool_defaults.max_size,
+            ),
+            min_idle: loader.maybe("database.pool.min_idle", "DATABASE_POOL_MIN_IDLE"),
+            connection_timeout: loader.optional_secs(
+                "database.pool.connection_timeout_secs",
+                "DATABASE_POOL_CONNECTION_TIMEOUT_SECS",
+                pool_defaults.connection_timeout,
+            ),
+            idle_timeout: loader
+                .maybe_secs(
+                    "database.pool.idle_timeout_secs",
+                    "DATABASE_POOL_IDLE_TIMEOUT_SECS",
+                )
+                .or(pool_defaults.idle_timeout),
+            max_lifetime: loader
+                .maybe_secs(
+                    "database.pool.max_lifetime_secs",
+                    "DATABASE_POOL_MAX_LIFETIME_SECS",
+                )
+                .or(pool_defaults.max_lifetime),
+        };
+        if pool.max_size == 0 {
+            loader.invalid(
+                "database.pool.max_size",
+                "DATABASE_POOL_MAX_SIZE",
+                "must be at least 1",
+            );
+        }
+        if pool
+            .min_idle
+            .is_some_and(|min_idle| min_idle > pool.max_size)
+        {
+            loader.invalid(
+                "database.pool.min_idle",
+                "DATABASE_POOL_MIN_IDLE",
+                "must not exceed database.pool.max_size",
+            );
+        }
+
+        let database = DatabaseSettings {
+            url: loader.required("database.url", "DATABASE_URL"),
+            pool,
+        };
+
+        let auth = AuthSettings {
+            jwt_secret: loader.required("auth.jwt_secret", "JWT_SECRET"),
+            hardened_mode: loader.flag(
+                "auth.hardened_mode",
+                "AUTH_HARDENED_MODE",
+                profile == Profile::Prod,
+            ),
+            session: SessionSettings {
+                cookies: loader.flag("auth.cookie_sessions", "AUTH_COOKIE_SESSIONS", false),
+                secure: loader.flag(
+                    "auth.cookie_secure",
+                    "AUTH_COOKIE_SECURE",
+                    profile != Profile::Dev,
+                ),
+                same_site: loader.optional(
+                    "auth.cookie_same_site",
+                    "AUTH_COOKIE_SAME_SITE",
+                    SameSite::Lax,
+                ),
+            },
+            admin_user_ids: loader.list("auth.admin_user_ids", "AUTH_ADMIN_USER_IDS", &[]),
+        };
+        if profile == Profile::Prod && auth.session.cookies && !auth.session.secure {
+            loader.invalid(
+                "auth.cookie_secure",
+                "AUTH_COOKIE_SECURE",
+                "must be enabled for cookie sessions in the prod profile",
+            );
+        }
+        if profile == Profile::Prod
+            && !auth.jwt_secret.is_empty()
+            && auth.jwt_secret.len() < MIN_PROD_JWT_SECRET_LEN
+        {
+            loader.invalid(
+                "auth.jwt_secret",
+                "JWT_SECRET",
+                format!(
+                    "must be at least {} characters in the prod profile",
+                    MIN_PROD_JWT_SECRET_LEN
+                ),
+            );
+        }
+
+        let google = GoogleSettings {
+            client_id: loader.required("google.client_id", "GOOGLE_CLIENT_ID"),
+            client_secret: loader.required("google.client_secret", "GOOGLE_CLIENT_SECRET"),
+            auth_url: loader.url(
+                "google.auth_url",
+                "GOOGLE_AUTH_URL",
+                Some("https://accounts.google.com/o/oauth2/v2/auth"),
+            ),
+            token_url: loader.url(
+                "google.token_url",
+                "GOOGLE_TOKEN_URL",
+                Some("https://oauth2.googleapis.com/token"),
+            ),
+            redirect_url: loader.url("google.redirect_url", "GOOGLE_REDIRECT_URL", None),
+            userinfo_url: loader.url(
+                "google.userinfo_url",
+                "GOOGLE_USERINFO_URL",
+                Some("https://www.googleapis.com/oauth2/v2/userinfo"),
+            ),
+        };
+
+        let telemetry = TelemetrySettings {
+            otlp_endpoint: loader
+                .maybe_url("telemetry.otlp_endpoint", "OTEL_EXPORTER_OTLP_ENDPOINT"),
+            service_name: loader.optional(
+                "telemetry.service_name",
+                "OTEL_SERVICE_NAME",
+                "queso-server".to_string(),
+            ),
+        };
+
+        let logging = LoggingSettings {
+            format: loader.optional(
+                "logging.format",
+                "LOG_FORMAT",
+                if profile == Profile::Prod {
+                    LogFormat::Json
+                } else {
+                    LogFormat::Text
+                },
+            ),
+            level: loader.optional("logging.level", "SERVER_LOG_LEVEL", LevelFilter::INFO),
+            overrides: loader.directives("logging.levels", "LOG_LEVELS"),
+        };
+
+        let rate_limit = RateLimitSettings {
+            enabled: loader.flag("rate_limit.enabled", "RATE_LIMIT_ENABLED", true),
+            store: loader.optional(
+                "rate_limit.store",
+                "RATE_LIMIT_STORE",
+                if profile == Profile::Prod {
+                    RateLimitBackend::Postgres
+                } else {
+                    RateLimitBackend::Memory
+                },
+            ),
+            // In prod the server runs behind the ALB
+            trusted_proxy_hops: loader.optional(
+                "rate_limit.trusted_proxy_hops",
+                "TRUSTED_PROXY_HOPS",
+                if profile == Profile::Prod { 1 } else { 0 },
+            ),
+        };
+
+        let idempotency = IdempotencySettings {
+            enabled: loader.flag("idempotency.enabled", "IDEMPOTENCY_ENABLED", true),
+            ttl: loader.optional_secs(
+                "idempotency.ttl_secs",
+                "IDEMPOTENCY_TTL_SECS",
+                Duration::from_secs(24 * 60 * 60),
+            ),
+        };
+
+        let users = UserSettings {
+            deleted_retention: loader.optional_secs(
+                "users.deleted_retention_secs",
+                "USERS_DELETED_RETENTION_SECS",
+                Duration::from_secs(30 * 24 * 60 * 60),
+            ),
+        };
+
+        let cors = CorsSettings {
+            // The Vite dev server runs on its own port
+            allowed_origins: loader.list(
+                "cors.allowed_origins",
+                "CORS_ALLOWED_ORIGINS",
+                if profile == Profile::Prod {
+                    &[]
+                } else {
+                    &["http://localhost:5173", "http://127.0.0.1:5173"]
+                },
+            ),
+            allow_credentials: loader.flag(
+                "cors.allow_credentials",
+                "CORS_ALLOW_CREDENTIALS",
+                true,
+            ),
+            max_age: loader.optional_secs(
+                "cors.max_age_secs",
+                "CORS_MAX_AGE_SECS",
+                Duration::from_secs(600),
+            ),
+        };
+
+        let security = SecuritySettings {
+            hsts: loader.flag("security.hsts", "SECURITY_HSTS", profile == Profile::Prod),
+            content_security_policy: loader.optional(
+                "security.content_security_policy",
+                "CONTENT_SECURITY_POLICY",
+                DEFAULT_CSP.to_string(),
+            ),
+        };
+        if axum::http::HeaderValue::from_str(&security.content_security_policy).is_err() {
+            loader.invalid(
+                "security.content_security_policy",
+                "CONTENT_SECURITY_POLICY",
+                "must be a valid header value",
+            );
+        }
+
+        if !loader.errors.is_empty() {
+            return Err(ConfigError::Invalid(loader.errors));
+        }
+
+        Ok(Self {
+            profile,
+            server,
+            database,
+            auth,
+            google,
+            telemetry,
+            logging,
+            rate_limit,
+            idempotency,
+            users,
+            cors,
+            security,
+        })
+    }
+}
+
+struct Flag(bool);
+
+impl FromStr for Flag {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(Flag(true)),
+            "false" | "0" | "no" | "off" => Ok(Flag(false)),
+            _ => Err("expected true/false, 1/0, yes/no or on/off"),
+        }
+    }
+}
+
+fn read_layer(path: &Path) -> Result<Option<Table>, ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(ConfigError::Read {
+                path: path.to_path_buf(),
+                source,
+            });
+        }
+    };
+
+    contents
+        .parse::<Table>()
+        .map(Some)
+        .map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Resolves individual keys across the layers, recording problems instead
+/// of stopping at the first one. Getters return a placeholder on failure;
+/// `from_sources` never hands out settings once an error was recorded.
+struct Loader<'a> {
+    layers: &'a [Table],
+    env: &'a HashMap<String, String>,
+    errors: Vec<SettingError>,
+}
+
+impl Loader<'_> {
+    fn invalid(&mut self, key: &'static str, env: &'static str, problem: impl Into<String>) {
+        self.errors.push(SettingError {
+            key,
+            env,
+            problem: problem.into(),
+        });
+    }
+
+    /// The raw value for `key`, preferring the environment over the files.
+    /// Empty environment values count as unset.
+    fn raw(&self, key: &str, env: &str) -> Option<String> {
+        if let Some(value) = self.env.get(env).filter(|value| !value.is_empty()) {
+            return Some(value.clone());
+        }
+
+        self.file_value(key).map(|value| match value {
+            Value::String(value) => value.clone(),
+            other => other.to_string(),
+        })
+    }
+
+    /// The value for `key` from the highest-precedence file that sets it.
+    fn file_value(&self, key: &str) -> Option<&Value> {
+        self.layers.iter().rev().find_map(|layer| {
+            let mut segments = key.split('.');
+            let first = layer.get(segments.next()?)?;
+            segments.try_fold(first, |value, segment| value.get(segment))
+        })
+    }
+
+    /// Filter directives, either `target=level,...` from the environment or
+    /// a `target = "level"` table in the files.
+    fn directives(&mut self, key: &'static str, env: &'static str) -> Vec<Directive> {
+        let entries: Vec<String> = match self.env.get(env).filter(|value| !value.is_empty()) {
+            Some(value) => value
+                .split(',')
+                .map(|entry| entry.trim().to_string())
+                .collect(),
+            None => match self.file_value(key) {
+                Some(Value::Table(table)) => table
+                    .iter()
+                    .map(|(target, level)| match level {
+                        Value::String(level) => format!("{}={}", target, level),
+                        other => format!("{}={}", target, other),
+                    })
+                    .collect(),
+                Some(other) => {
+                    self.invalid(
+                        key,
+                        env,
+                        format!("expected a table, got {}", other.type_str()),
+                    );
+                    return Vec::new();
+                }
+                None => return Vec::new(),
+            },
+        };
+
+        entries
+            .into_iter()
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| match entry.parse::<Directive>() {
+                Ok(directive) => Some(directive),
+                Err(e) => {
+                    self.invalid(key, env, format!("invalid directive {:?}: {}", entry, e));
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// A list, either comma-separated from the environment or an array in
+    /// the files. Every invalid entry is reported.
+    fn list<T>(&mut self, key: &'static str, env: &'static str, default: &[&str]) -> Vec<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let entries: Vec<String> = match self.env.get(env).filter(|value| !value.is_empty()) {
+            Some(value) => value
+                .split(',')
+                .map(|entry| entry.trim().to_string())
+                .collect(),
+            None => match self.file_value(key) {
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(value) => value.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect(),
+                Some(other) => {
+                    self.invalid(
+                        key,
+                        env,
+                        format!("expected an array, got {}", other.type_str()),
+                    );
+                    return Vec::new();
+                }
+                None => default.iter().map(ToString::to_string).collect(),
+            },
+        };
+
+        entries
+            .into_iter()
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| match entry.parse() {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    self.invalid(key, env, format!("invalid entry {:?}: {}", entry, e));
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn maybe<T>(&mut self, key: &'static str, env: &'static str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let raw = self.raw(key, env)?;
+        match raw.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.invalid(key, env, format!("invalid value {:?}: {}", raw, e));
+                None
+            }
+        }
+    }
+
+    /// A boolean, also accepting `1`/`0`, `yes`/`no` and `on`/`off` as
+    /// older deployments set them.
+    fn flag(&mut self, key: &'static str, env: &'static str, default: bool) -> bool {
+        self.maybe(key, env)
+            .map(|Flag(value)| value)
+            .unwrap_or(default)
+    }
+
+    fn optional<T>(&mut self, key: &'static str, env: &'static str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.maybe(key, env).unwrap_or(default)
+    }
+
+    fn required<T>(&mut self, key: &'static str, env: &'static str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: fmt::Display,
+    {
+        if self.raw(key, env).is_none() {
+            self.invalid(key, env, "is required");
+        }
+        self.maybe(key, env).unwrap_or_default()
+    }
+
+    fn maybe_secs(&mut self, key: &'static str, env: &'static str) -> Option<Duration> {
+        self.maybe(key, env).map(Duration::from_secs)
+    }
+
+    fn optional_secs(
+        &mut self,
+        key: &'static str,
+        env: &'static str,
+        default: Duration,
+    ) -> Duration {
+        self.maybe_secs(key, env).unwrap_or(default)
+    }
+
+    fn url(&mut self, key: &'static str, env: &'static str, default: Option<&str>) -> String {
+        match (self.maybe_url(key, env), default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => default.to_string(),
+            (None, None) => {
+                if self.raw(key, env).is_none() {
+                    self.invalid(key, env, "is required");
+                }
+                String::new()
+            }
+        }
+    }
+
+    fn maybe_url(&mut self, key: &'static str, env: &'static str) -> Option<String> {
+        let value = self.raw(key, env)?;
+        match url::Url::parse(&value) {
+            Ok(_) => Some(value),
+            Err(e) => {
+                self.invalid(key, env, format!("invalid URL {:?}: {}", value, e));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn complete_env() -> HashMap<String, String> {
+        env(&[
+            ("DATABASE_URL", "postgres://localhost/queso"),
+            ("JWT_SECRET", "a-secret-that-is-long-enough-for-prod"),
+            ("GOOGLE_CLIENT_ID", "client-id"),
+            ("GOOGLE_CLIENT_SECRET", "client-secret"),
+            (
+                "GOOGLE_REDIRECT_URL",
+                "http://localhost:5173/auth/google/callback",
+            ),
+        ])
+    }
+
+    fn invalid_keys(result: Result<Settings, ConfigError>) -> Vec<&'static str> {
+        match result {
+            Err(ConfigError::Invalid(errors)) => errors.iter().map(|error| error.key).collect(),
+            other => panic!("expected invalid configuration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_env_only_settings_use_defaults() {
+        let settings = Settings::from_sources(Profile::Dev, &[], &complete_env()).unwrap();
+
+        assert_eq!(settings.server.addr(), "127.0.0.1:3000");
+        assert_eq!(settings.server.pre_stop_delay, Duration::ZERO);
+        assert_eq!(settings.database.pool.max_size, 10);
+        assert!(!settings.auth.hardened_mode);
+        assert!(settings.auth.admin_user_ids.is_empty());
+        assert_eq!(
+            settings.google.userinfo_url,
+            "https://www.googleapis.com/oauth2/v2/userinfo"
+        );
+        assert_eq!(settings.cors.allowed_origins.len(), 2);
+        assert!(!settings.security.hsts);
+        assert!(
+            settings
+                .security
+                .content_security_policy
+                .contains("'nonce-{nonce}'")
+        );
+        assert!(settings.rate_limit.enabled);
+        assert_eq!(settings.rate_limit.store, RateLimitBackend::Memory);
+        assert_eq!(settings.rate_limit.trusted_proxy_hops, 0);
+        assert!(settings.idempotency.enabled);
+        assert_eq!(settings.idempotency.ttl, Duration::from_secs(24 * 60 * 60));
+        assert_eq!(
+            settings.users.deleted_retention,
+            Duration::from_secs(30 * 24 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn test_every_problem_is_reported() {
+        let vars = env(&[
+            ("API_PORT", "not-a-port"),
+            ("DATABASE_POOL_MAX_SIZE", "0"),
+            ("GOOGLE_TOKEN_URL", "not a url"),
+        ]);
+
+        let keys = invalid_keys(Settings::from_sources(Profile::Dev, &[], &vars));
+
+        assert_eq!(
+            keys,
+            [
+                "server.port",
+                "database.pool.max_size",
+                "database.url",
+                "auth.jwt_secret",
+                "google.client_id",
+                "google.client_secret",
+                "google.token_url",
+                "google.redirect_url",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layers_and_env_precedence() {
+        let base: Table = toml::toml! {
+            [server]
+            host = "0.0.0.0"
+            port = 8080
+
+            [database.pool]
+            max_size = 20
+        };
+        let profile: Table = toml::toml! {
+            [server]
+            port = 9090
+        };
+        let mut vars = complete_env();
+        vars.insert("DATABASE_POOL_MAX_SIZE".into(), "30".into());
+
+        let settings = Settings::from_sources(Profile::Dev, &[base, profile], &vars).unwrap();
+
+        assert_eq!(settings.server.addr(), "0.0.0.0:9090");
+        assert_eq!(settings.database.pool.max_size, 30);
+    }
+
+    #[test]
+    fn test_log_level_overrides() {
+        let file: Table = toml::toml! {
+            [logging.levels]
+            tower_http = "debug"
+            "queso_server::features::auth" = "trace"
+        };
+        let settings =
+            Settings::from_sources(Profile::Dev, std::slice::from_ref(&file), &complete_env())
+                .unwrap();
+        let overrides: Vec<String> = settings
+            .logging
+            .overrides
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            overrides,
+            ["queso_server::features::auth=trace", "tower_http=debug"]
+        );
+
+        let mut vars = complete_env();
+        vars.insert("LOG_LEVELS".into(), "diesel=warn, tower_http=loud".into());
+        vars.insert("LOG_FORMAT".into(), "xml".into());
+        let keys = invalid_keys(Settings::from_sources(Profile::Dev, &[file], &vars));
+        assert_eq!(keys, ["logging.format", "logging.levels"]);
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        let file: Table = toml::toml! {
+            [cors]
+            allowed_origins = ["https://queso.dev", "https://*.queso.dev"]
+        };
+        let settings =
+            Settings::from_sources(Profile::Prod, std::slice::from_ref(&file), &complete_env())
+                .unwrap();
+        let origins: Vec<String> = settings
+            .cors
+            .allowed_origins
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(origins, ["https://queso.dev", "https://*.queso.dev"]);
+
+        let mut vars = complete_env();
+        vars.insert(
+            "CORS_ALLOWED_ORIGINS".into(),
+            "https://queso.dev, *, https://queso.dev/app".into(),
+        );
+        let result = Settings::from_sources(Profile::Prod, &[file], &vars);
+        assert_eq!(
+            invalid_keys(result),
+            ["cors.allowed_origins", "cors.allowed_origins"]
+        );
+    }
+
+    #[test]
+    fn test_admin_user_ids() {
+        let mut vars = complete_env();
+        vars.insert("AUTH_ADMIN_USER_IDS".into(), "1, 7".into());
+        let settings = Settings::from_sources(Profile::Dev, &[], &vars).unwrap();
+        assert_eq!(settings.auth.admin_user_ids, [1, 7]);
+
+        vars.insert("AUTH_ADMIN_USER_IDS".into(), "1, ana".into());
+        let result = Settings::from_sources(Profile::Dev, &[], &vars);
+        assert_eq!(invalid_keys(result), ["auth.admin_user_ids"]);
+    }
+
+    #[test]
+    fn test_flags_accept_common_spellings() {
+        let mut vars = complete_env();
+        vars.insert("AUTH_HARDENED_MODE".into(), "1".into());
+        vars.insert("AUTH_COOKIE_SESSIONS".into(), "Yes".into());
+        vars.insert("RATE_LIMIT_ENABLED".into(), "0".into());
+        vars.insert("SECURITY_HSTS".into(), "on".into());
+        let settings = Settings::from_sources(Profile::Dev, &[], &vars).unwrap();
+        assert!(settings.auth.hardened_mode);
+        assert!(settings.auth.session.cookies);
+        assert!(!settings.rate_limit.enabled);
+        assert!(settings.security.hsts);
+
+        let files = [toml::toml! {
+            [idempotency]
+            enabled = false
+        }];
+        vars.insert("AUTH_HARDENED_MODE".into(), "maybe".into());
+        let result = Settings::from_sources(Profile::Dev, &files, &vars);
+        assert_eq!(invalid_keys(result), ["auth.hardened_mode"]);
+        vars.remove("AUTH_HARDENED_MODE");
+        let settings = Settings::from_sources(Profile::Dev, &files, &vars).unwrap();
+        assert!(!settings.idempotency.enabled);
+    }
+
+    #[test]
+    fn test_prod_profile_is_stricter() {
+        let mut vars = complete_env();
+        let settings = Settings::from_sources(Profile::Prod, &[], &vars).unwrap();
+        assert!(settings.auth.hardened_mode);
+        assert_eq!(settings.server.pre_stop_delay, Duration::from_secs(5));
+        assert_eq!(settings.logging.format, LogFormat::Json);
+        assert_eq!(settings.rate_limit.store, RateLimitBackend::Postgres);
+        assert_eq!(settings.rate_limit.trusted_proxy_hops, 1);
+        assert!(settings.cors.allowed_origins.is_empty());
+        assert!(settings.security.hsts);
+
+        vars.insert("JWT_SECRET".into(), "short".into());
+        vars.insert("AUTH_COOKIE_SESSIONS".into(), "true".into());
+        vars.insert("AUTH_COOKIE_SECURE".into(), "false".into());
+        let keys = invalid_keys(Settings::from_sources(Profile::Prod, &[], &vars));
+        assert_eq!(keys, ["auth.cookie_secure", "auth.jwt_secret"]);
+    }
+
+    #[test]
+    fn test_secrets_are_not_debug_printed() {
+        let settings = Settings::from_sources(Profile::Dev, &[], &complete_env()).unwrap();
+        let debug = format!("{:?}", settings);
+
+        assert!(!debug.contains("a-secret-that-is-long-enough-for-prod"));
+        assert!(!debug.contains("client-secret"));
+    }
+}
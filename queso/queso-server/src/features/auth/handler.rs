@@ -1,75 +1,151 @@
-use axum::{Json, extract::State};
+use axum::{Json, extract::State, response::Response};
 
 use super::{
-    model::{AuthError, AuthUser, EmailLoginRequest, LoginResponse},
+    model::{AuthUser, CurrentUser, EmailLoginRequest, LoginResponse},
     oauth::OAuthState,
 };
-use crate::features::users::model::User;
+use crate::api::{ApiError, ProblemDetails, ValidatedJson};
 
 /// Login with email and password
 #[utoipa::path(
     post,
-    path = "/api/auth/login",
+    path = "/auth/login/email",
     request_body = EmailLoginRequest,
     responses(
-        (status = 200, description = "Successfully logged in", body = LoginResponse),
-        (status = 401, description = "Invalid credentials"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Successfully logged in; with cookie sessions the token is set as an HttpOnly cookie instead of returned", body = LoginResponse),
+        (status = 400, description = "No account with this email; hardened mode answers 401 instead", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid login data", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     ),
     tag = "auth"
 )]
 pub async fn login(
     State(state): State<OAuthState>,
-    Json(payload): Json<EmailLoginRequest>,
-) -> Result<Json<LoginResponse>, AuthError> {
+    ValidatedJson(payload): ValidatedJson<EmailLoginRequest>,
+) -> Result<Response, ApiError> {
     let response = state.auth_service.login_with_email(payload).await?;
-    Ok(Json(response))
+    Ok(state.auth_service.sessions().login(response))
 }
 
 /// Get current user information
 #[utoipa::path(
     get,
-    path = "/api/auth/me",
+    path = "/auth/me",
     responses(
-        (status = 200, description = "Successfully retrieved user information", body = User),
-        (status = 401, description = "Unauthorized"),
-        (status = 404, description = "User not found")
+        (status = 200, description = "Successfully retrieved user information", body = CurrentUser),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "User not found", body = ProblemDetails, content_type = "application/problem+json")
     ),
     security(
-        ("jwt" = [])
+        ("jwt" = []),
+        ("session" = [])
     ),
     tag = "auth"
 )]
 pub async fn me(
     State(state): State<OAuthState>,
     auth_user: AuthUser,
-) -> Result<Json<serde_json::Value>, AuthError> {
+) -> Result<Json<CurrentUser>, ApiError> {
     let user = state.auth_service.get_user(auth_user.user_id).await?;
-    Ok(Json(serde_json::json!({
-        "id": user.id,
-        "username": user.username,
-        "email": user.email,
-    })))
+    Ok(Json(user.into()))
 }
 
 /// Logout current user
 #[utoipa::path(
     post,
-    path = "/api/auth/logout",
+    path = "/auth/logout",
     responses(
         (status = 200, description = "Successfully logged out"),
-        (status = 401, description = "Unauthorized")
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Cookie session without a matching CSRF token", body = ProblemDetails, content_type = "application/problem+json")
     ),
     security(
-        ("jwt" = [])
+        ("jwt" = []),
+        ("session" = [])
     ),
     tag = "auth"
 )]
-pub async fn logout(State(state): State<OAuthState>, auth_user: AuthUser) -> Result<(), AuthError> {
+pub async fn logout(
+    State(state): State<OAuthState>,
+    auth_user: AuthUser,
+) -> Result<Response, ApiError> {
     // Invalidate the user's session/token
     state
         .auth_service
         .invalidate_session(auth_user.user_id)
         .await?;
-    Ok(())
+    Ok(state.auth_service.sessions().logout())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::request_id::REQUEST_ID_HEADER,
+        config::database::PoolSettings,
+        features::users::{NewUser, UserRepository, UserService},
+        server,
+        shutdown::Shutdown,
+        test_support::{self, TestDatabase},
+    };
+    use axum::{
+        Router,
+        body::{Body, Bytes},
+        http::{Request, StatusCode, header},
+    };
+    use tower::ServiceExt;
+
+    async fn login(app: &Router, email: &str, password: &str) -> (StatusCode, Bytes) {
+        let request = Request::post("/api/v1/auth/login/email")
+            .header(header::CONTENT_TYPE, "application/json")
+            // A fixed request ID, so only the outcome can differ
+            .header(&REQUEST_ID_HEADER, "login-test")
+            .body(Body::from(format!(
+                r#"{{"email":"{}","password":"{}"}}"#,
+                email, password
+            )))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, body)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_login_failures_in_hardened_mode_are_identical() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let new_user = NewUser::from_request(
+            "ana".to_string(),
+            "ana@example.com".to_string(),
+            "correct horse battery".to_string(),
+        )
+        .unwrap();
+        UserService::new(UserRepository::new(db.pool.clone()))
+            .create_user(new_user)
+            .await
+            .unwrap();
+
+        let mut settings = test_support::settings();
+        settings.auth.hardened_mode = true;
+        let app = server::app(&settings, db.pool.clone(), &Shutdown::new());
+
+        let unknown = login(&app, "nobody@example.com", "correct horse battery").await;
+        let wrong_password = login(&app, "ana@example.com", "wrong password").await;
+        assert_eq!(unknown.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(unknown, wrong_password);
+
+        // Outside hardened mode unknown accounts are still reported as such
+        settings.auth.hardened_mode = false;
+        let app = server::app(&settings, db.pool.clone(), &Shutdown::new());
+
+        let (status, body) = login(&app, "nobody@example.com", "correct horse battery").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "user_not_found");
+        let (status, _) = login(&app, "ana@example.com", "wrong password").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
 }
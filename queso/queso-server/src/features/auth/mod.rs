@@ -3,7 +3,10 @@ pub mod model;
 pub mod oauth;
 pub mod router;
 pub mod service;
+pub mod session;
 
-pub use model::{AuthError, AuthUser, Claims, EmailLoginRequest, LoginResponse};
+pub use model::{
+    AdminUser, Admins, AuthError, AuthUser, Claims, CurrentUser, EmailLoginRequest, LoginResponse,
+};
 pub use router::auth_routes;
 pub use service::AuthService;
@@ -1,25 +1,26 @@
 use async_trait::async_trait;
 use axum::{
-    Json, RequestPartsExt,
-    extract::FromRequestParts,
-    http::{StatusCode, request::Parts},
-    response::{IntoResponse, Response},
-};
-use axum_extra::{
-    TypedHeader,
-    headers::{Authorization, authorization::Bearer},
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
 };
 use chrono::{Duration, Utc};
 use jsonwebtoken::{DecodingKey, EncodingKey, Validation};
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::env;
+use std::collections::HashSet;
 use std::future::Future;
+use std::sync::Arc;
 use thiserror::Error;
 use utoipa::ToSchema;
+use validator::Validate;
+
+use super::session;
+use crate::{
+    api::ApiError,
+    features::users::model::{User, UserError},
+};
 
-use crate::features::users::model::UserError;
+/// How long issued tokens, and the session cookies carrying them, are valid.
+pub const TOKEN_LIFETIME: Duration = Duration::hours(24);
 
 #[derive(Debug, Error)]
 pub enum AuthError {
@@ -27,55 +28,85 @@ pub enum AuthError {
     InvalidCredentials(String),
     #[error("Missing credentials")]
     MissingCredentials,
+    /// No account for the submitted login; only reported outside hardened
+    /// mode
+    #[error("User not found")]
+    UnknownAccount,
+    #[error("Invalid token: {0}")]
+    InvalidToken(jsonwebtoken::errors::Error),
     #[error("Token creation error: {0}")]
     TokenCreation(#[from] jsonwebtoken::errors::Error),
     #[error("User error: {0}")]
     UserError(#[from] UserError),
     #[error("OAuth error: {0}")]
     OAuthError(String),
+    /// The Google account belongs to a deleted user that has not been
+    /// purged yet
+    #[error("Account deleted")]
+    AccountDeleted,
+    #[error("CSRF token missing or mismatched")]
+    CsrfMismatch,
+    /// Authenticated, but not allowed to act on the resource
+    #[error("Forbidden")]
+    Forbidden,
 }
 
-impl IntoResponse for AuthError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AuthError::InvalidCredentials(msg) => (StatusCode::UNAUTHORIZED, msg),
+impl From<AuthError> for ApiError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::InvalidCredentials(msg) => {
+                ApiError::unauthorized("invalid_credentials", msg)
+            }
             AuthError::MissingCredentials => {
-                (StatusCode::UNAUTHORIZED, "Missing credentials".to_string())
+                ApiError::unauthorized("missing_credentials", "Missing credentials")
+            }
+            AuthError::UnknownAccount => ApiError::bad_request("user_not_found", "User not found"),
+            AuthError::InvalidToken(e) => {
+                ApiError::unauthorized("invalid_token", "Invalid or expired token").with_internal(e)
+            }
+            AuthError::TokenCreation(e) => {
+                ApiError::internal("token_creation_failed", "Failed to create token")
+                    .with_internal(e)
+            }
+            AuthError::UserError(e) => ApiError::from(e),
+            AuthError::OAuthError(msg) => {
+                ApiError::unauthorized("oauth_error", "OAuth authentication failed")
+                    .with_internal(msg)
             }
-            AuthError::TokenCreation(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create token".to_string(),
-            ),
-            AuthError::UserError(e) => (StatusCode::BAD_REQUEST, e.to_string()),
-            AuthError::OAuthError(msg) => (StatusCode::UNAUTHORIZED, msg),
-        };
-
-        let body = Json(json!({
-            "error": error_message,
-        }));
-
-        (status, body).into_response()
+            AuthError::AccountDeleted => {
+                ApiError::forbidden("account_deleted", "This account has been deleted")
+            }
+            AuthError::CsrfMismatch => {
+                ApiError::forbidden("csrf_token_mismatch", "Missing or invalid CSRF token")
+            }
+            AuthError::Forbidden => {
+                ApiError::forbidden("forbidden", "You are not allowed to do this")
+            }
+        }
     }
 }
 
-pub(crate) static KEYS: Lazy<Keys> = Lazy::new(|| {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    Keys::new(secret.as_bytes())
-});
-
+/// JWT signing and verification keys derived from `auth.jwt_secret`.
 #[derive(Clone)]
-pub(crate) struct Keys {
+pub struct Keys {
     pub(crate) encoding: EncodingKey,
     pub(crate) decoding: DecodingKey,
 }
 
 impl Keys {
-    fn new(secret: &[u8]) -> Self {
+    pub fn new(secret: &[u8]) -> Self {
         Self {
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
         }
     }
+
+    /// Checks a bearer token's signature and expiry.
+    pub fn verify(&self, token: &str) -> Result<Claims, AuthError> {
+        jsonwebtoken::decode::<Claims>(token, &self.decoding, &Validation::default())
+            .map(|data| data.claims)
+            .map_err(AuthError::InvalidToken)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -90,7 +121,7 @@ pub struct Claims {
 impl Claims {
     pub fn new(user_id: i32) -> Self {
         let now = Utc::now().timestamp() as usize;
-        let exp = (Utc::now() + Duration::hours(24)).timestamp() as usize;
+        let exp = (Utc::now() + TOKEN_LIFETIME).timestamp() as usize;
 
         Self {
             sub: user_id.to_string(),
@@ -102,6 +133,11 @@ impl Claims {
     }
 }
 
+/// The authenticated caller, from a bearer token or the session cookie.
+///
+/// Cookies are sent by the browser automatically, so unsafe requests
+/// authenticated by cookie must also echo the CSRF cookie in
+/// `X-CSRF-Token`.
 #[derive(Debug)]
 pub struct AuthUser {
     pub user_id: i32,
@@ -110,30 +146,73 @@ pub struct AuthUser {
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthUser
 where
+    Arc<Keys>: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = AuthError;
+    type Rejection = ApiError;
 
     #[allow(clippy::manual_async_fn)]
     fn from_request_parts(
         parts: &mut Parts,
-        _state: &S,
+        state: &S,
     ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
+        let keys = Arc::<Keys>::from_ref(state);
         async move {
-            let TypedHeader(Authorization(bearer)) = parts
-                .extract::<TypedHeader<Authorization<Bearer>>>()
-                .await
-                .map_err(|_| AuthError::MissingCredentials)?;
-
-            let token_data = jsonwebtoken::decode::<Claims>(
-                bearer.token(),
-                &KEYS.decoding,
-                &Validation::default(),
-            )
-            .map_err(AuthError::TokenCreation)?;
+            // Cookie sessions were already checked for CSRF by `session::csrf`
+            let credential =
+                session::credential(&parts.headers).ok_or(AuthError::MissingCredentials)?;
+            let claims = keys.verify(credential.token())?;
 
+            tracing::Span::current().record("user_id", claims.user_id);
             Ok(AuthUser {
-                user_id: token_data.claims.user_id,
+                user_id: claims.user_id,
+            })
+        }
+    }
+}
+
+/// Users allowed to call the admin endpoints, from `auth.admin_user_ids`.
+#[derive(Debug, Clone, Default)]
+pub struct Admins(Arc<HashSet<i32>>);
+
+impl Admins {
+    pub fn new(user_ids: impl IntoIterator<Item = i32>) -> Self {
+        Self(Arc::new(user_ids.into_iter().collect()))
+    }
+
+    pub fn contains(&self, user_id: i32) -> bool {
+        self.0.contains(&user_id)
+    }
+}
+
+/// An authenticated caller listed in [`Admins`]. Anyone else is refused with
+/// `403 Forbidden`.
+#[derive(Debug)]
+pub struct AdminUser {
+    pub user_id: i32,
+}
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    Arc<Keys>: FromRef<S>,
+    Admins: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    #[allow(clippy::manual_async_fn)]
+    fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
+        let admins = Admins::from_ref(state);
+        async move {
+            let user = AuthUser::from_request_parts(parts, state).await?;
+            if !admins.contains(user.user_id) {
+                return Err(AuthError::Forbidden.into());
+            }
+            Ok(AdminUser {
+                user_id: user.user_id,
             })
         }
     }
@@ -145,25 +224,54 @@ pub struct UsernameLoginRequest {
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct EmailLoginRequest {
+    #[validate(length(min = 1, max = 254, message = "must be between 1 and 254 characters"))]
+    #[schema(max_length = 254)]
     pub email: String,
+    #[validate(length(min = 1, max = 128, message = "must be between 1 and 128 characters"))]
+    #[schema(max_length = 128, format = "password")]
     pub password: String,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
-    pub token: String,
+    /// Bearer token; omitted when cookie sessions are enabled and the token
+    /// is set as an `HttpOnly` cookie instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
 }
 
 impl LoginResponse {
     pub fn new(token: String) -> Self {
-        Self { token }
+        Self { token: Some(token) }
+    }
+}
+
+/// The signed-in user, without the account details `User` carries.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+}
+
+impl From<User> for CurrentUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+        }
     }
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub struct OAuthCallback {
+    #[validate(length(min = 1, max = 2048, message = "must be between 1 and 2048 characters"))]
+    #[schema(max_length = 2048)]
     pub code: String,
+    #[validate(length(min = 1, max = 512, message = "must be between 1 and 512 characters"))]
+    #[schema(max_length = 512)]
     pub state: String,
 }
@@ -1,23 +1,37 @@
-use axum::{Json, extract::State, response::IntoResponse};
+use axum::{
+    Json,
+    extract::{FromRef, State},
+    response::{IntoResponse, Response},
+};
 use base64::{Engine as _, engine::general_purpose::URL_SAFE};
 use oauth2::{
     AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointNotSet, EndpointSet,
     PkceCodeChallenge, RedirectUrl, Scope, TokenResponse, TokenUrl, basic::BasicClient,
 };
 use serde::Serialize;
+use std::sync::Arc;
 use utoipa::ToSchema;
 
-use crate::features::users::{model::GoogleUser, service::UserService};
+use crate::{
+    api::{ApiError, ProblemDetails, ValidatedJson},
+    config::settings::GoogleSettings,
+    features::users::{
+        model::{GoogleUser, NewUser, UserError},
+        service::UserService,
+    },
+    telemetry::{metrics, trace},
+};
 
 use super::{
-    model::{AuthError, LoginResponse, OAuthCallback},
-    service::AuthService,
+    model::{AuthError, Keys, LoginResponse, OAuthCallback},
+    service::{AuthService, login_outcome},
 };
 
 #[derive(Clone)]
 pub struct OAuthConfig {
     pub client:
         BasicClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet>,
+    pub userinfo_url: String,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -26,24 +40,18 @@ pub struct OAuthUrlResponse {
 }
 
 impl OAuthConfig {
-    pub fn new(
-        client_id: String,
-        client_secret: String,
-        auth_url: String,
-        token_url: String,
-        redirect_url: String,
-    ) -> Result<Self, AuthError> {
-        let client = BasicClient::new(ClientId::new(client_id))
-            .set_client_secret(ClientSecret::new(client_secret))
-            .set_redirect_uri(
-                RedirectUrl::new(redirect_url).map_err(|e| AuthError::OAuthError(e.to_string()))?,
-            )
-            .set_auth_uri(AuthUrl::new(auth_url).map_err(|e| AuthError::OAuthError(e.to_string()))?)
-            .set_token_uri(
-                TokenUrl::new(token_url).map_err(|e| AuthError::OAuthError(e.to_string()))?,
-            );
+    pub fn new(settings: &GoogleSettings) -> Result<Self, AuthError> {
+        let oauth_error = |e: url::ParseError| AuthError::OAuthError(e.to_string());
+        let client = BasicClient::new(ClientId::new(settings.client_id.clone()))
+            .set_client_secret(ClientSecret::new(settings.client_secret.clone()))
+            .set_redirect_uri(RedirectUrl::new(settings.redirect_url.clone()).map_err(oauth_error)?)
+            .set_auth_uri(AuthUrl::new(settings.auth_url.clone()).map_err(oauth_error)?)
+            .set_token_uri(TokenUrl::new(settings.token_url.clone()).map_err(oauth_error)?);
 
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            userinfo_url: settings.userinfo_url.clone(),
+        })
     }
 }
 
@@ -55,17 +63,23 @@ pub struct OAuthState {
     pub user_service: UserService,
 }
 
+impl FromRef<OAuthState> for Arc<Keys> {
+    fn from_ref(state: &OAuthState) -> Self {
+        state.auth_service.keys()
+    }
+}
+
 /// Initiate Google OAuth login
 #[utoipa::path(
     get,
-    path = "/api/auth/google/login",
+    path = "/auth/google/login",
     responses(
         (status = 200, description = "Successfully generated OAuth URL", body = OAuthUrlResponse),
-        (status = 500, description = "Failed to generate OAuth URL")
+        (status = 500, description = "Failed to generate OAuth URL", body = ProblemDetails, content_type = "application/problem+json")
     ),
     tag = "auth"
 )]
-pub async fn google_login(State(state): State<OAuthState>) -> Result<impl IntoResponse, AuthError> {
+pub async fn google_login(State(state): State<OAuthState>) -> Result<impl IntoResponse, ApiError> {
     // Generate a PKCE challenge
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
@@ -91,20 +105,33 @@ pub async fn google_login(State(state): State<OAuthState>) -> Result<impl IntoRe
 /// Handle Google OAuth callback
 #[utoipa::path(
     post,
-    path = "/api/auth/google/callback",
+    path = "/auth/google/callback",
     request_body = OAuthCallback,
     responses(
         (status = 200, description = "Successfully authenticated with Google", body = LoginResponse),
-        (status = 400, description = "Invalid callback parameters"),
-        (status = 500, description = "Authentication failed")
+        (status = 400, description = "Malformed callback body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Authentication with Google failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The Google account's user was deleted", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid callback parameters", body = ProblemDetails, content_type = "application/problem+json")
     ),
     tag = "auth"
 )]
 pub async fn google_callback(
     State(state): State<OAuthState>,
-    Json(params): Json<OAuthCallback>,
-) -> Result<Json<LoginResponse>, AuthError> {
+    ValidatedJson(params): ValidatedJson<OAuthCallback>,
+) -> Result<Response, ApiError> {
+    let result = authenticate_with_google(&state, params).await;
+    metrics::record_login("google", login_outcome(&result));
+    Ok(state.auth_service.sessions().login(result?))
+}
+
+async fn authenticate_with_google(
+    state: &OAuthState,
+    params: OAuthCallback,
+) -> Result<LoginResponse, AuthError> {
+    // Both Google calls carry the request's `traceparent`
     let http_client = reqwest::Client::builder()
+        .default_headers(trace::propagation_headers())
         .redirect(reqwest::redirect::Policy::none())
         .build()
         .map_err(|e| AuthError::OAuthError(e.to_string()))?;
@@ -127,10 +154,9 @@ pub async fn google_callback(
 
     // Get user info from Google
     let client = reqwest::Client::new();
-    let userinfo_url =
-        std::env::var("GOOGLE_USERINFO_URL").expect("GOOGLE_USERINFO_URL must be set");
     let user_data = client
-        .get(userinfo_url)
+        .get(&state.oauth_config.userinfo_url)
+        .headers(trace::propagation_headers())
         .bearer_auth(token.access_token().secret())
         .send()
         .await
@@ -142,15 +168,123 @@ pub async fn google_callback(
     // Check if user exists by google_id
     let user = match state.user_service.find_by_google_id(&user_data.id).await {
         Ok(user) => user,
-        Err(_) => {
+        Err(UserError::NotFound) => {
+            // A deleted user keeps its Google ID and email until it is
+            // purged, so signing up again would only clash with it
+            if state
+                .user_service
+                .is_deleted_google_account(&user_data.id)
+                .await?
+            {
+                return Err(AuthError::AccountDeleted);
+            }
+
             // Create new user if not exists
-            let new_user = crate::features::users::model::NewUser::from_google_user(user_data);
+            let new_user = NewUser::from_google_user(user_data);
             state.user_service.create_user(new_user).await?
         }
+        Err(e) => return Err(e.into()),
     };
 
     // Generate JWT token
     let token = state.auth_service.generate_token(user.id)?;
 
-    Ok(Json(LoginResponse::new(token)))
+    Ok(LoginResponse::new(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::database::PoolSettings,
+        features::{auth::router::auth_routes, users::UserRepository},
+        test_support::TestDatabase,
+    };
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode, header},
+        routing::{get, post},
+    };
+    use tower::ServiceExt;
+
+    /// Serves Google's token and userinfo endpoints on a local port, always
+    /// for the same account.
+    async fn fake_google() -> GoogleSettings {
+        let google = Router::new()
+            .route(
+                "/token",
+                post(|| async {
+                    Json(serde_json::json!({
+                        "access_token": "google-token",
+                        "token_type": "bearer",
+                    }))
+                }),
+            )
+            .route(
+                "/userinfo",
+                get(|| async {
+                    Json(serde_json::json!({
+                        "id": "google-1",
+                        "email": "ana@example.com",
+                        "name": "ana",
+                        "verified_email": true,
+                        "given_name": "Ana",
+                        "family_name": "Bell",
+                        "picture": "https://example.com/ana.png",
+                        "locale": "en",
+                    }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, google).await });
+
+        GoogleSettings {
+            client_id: "client-id".to_string(),
+            client_secret: "client-secret".to_string(),
+            auth_url: format!("{}/auth", url),
+            token_url: format!("{}/token", url),
+            redirect_url: "http://localhost:5173/auth/google/callback".to_string(),
+            userinfo_url: format!("{}/userinfo", url),
+        }
+    }
+
+    fn callback() -> Request<Body> {
+        let state = URL_SAFE.encode("pkce-verifier");
+        Request::post("/google/callback")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(
+                r#"{{"code":"google-code","state":"{}"}}"#,
+                state
+            )))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_deleted_google_users_cannot_sign_in_again() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let user_service = UserService::new(UserRepository::new(db.pool.clone()));
+        let app = auth_routes().with_state(OAuthState {
+            oauth_config: OAuthConfig::new(&fake_google().await).unwrap(),
+            auth_service: AuthService::new(user_service.clone(), Keys::new(b"oauth-test")),
+            user_service: user_service.clone(),
+        });
+
+        let response = app.clone().oneshot(callback()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let user = user_service.find_by_google_id("google-1").await.unwrap();
+        user_service.delete_user(user.id, None).await.unwrap();
+
+        let response = app.oneshot(callback()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "account_deleted");
+        // Not signed up again under a new account
+        assert!(user_service.get_users().await.unwrap().is_empty());
+    }
 }
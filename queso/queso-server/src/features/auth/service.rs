@@ -1,22 +1,81 @@
-use crate::features::users::{model::User, service::UserService};
+use crate::{
+    features::users::{
+        model::{User, UserError},
+        service::UserService,
+    },
+    telemetry::metrics::{self, LoginOutcome},
+};
 use argon2::{
     Argon2,
-    password_hash::{PasswordHash, PasswordVerifier},
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
 use jsonwebtoken::{Header, encode};
+use once_cell::sync::Lazy;
+use std::sync::Arc;
 
-use super::model::{
-    AuthError, Claims, EmailLoginRequest, KEYS, LoginResponse, UsernameLoginRequest,
+use super::{
+    model::{AuthError, Claims, EmailLoginRequest, Keys, LoginResponse, UsernameLoginRequest},
+    session::Sessions,
 };
+use crate::config::settings::{SameSite, SessionSettings};
+
+// Hash verified against when no usable account exists, so a login for an
+// unknown user costs the same Argon2 work as one with a wrong password
+static DUMMY_PASSWORD_HASH: Lazy<String> = Lazy::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(b"queso-dummy-password", &salt)
+        .expect("Failed to hash dummy password")
+        .to_string()
+});
+
+const UNIFORM_LOGIN_FAILURE: &str = "Invalid email or password";
 
 #[derive(Clone)]
 pub struct AuthService {
     user_service: UserService,
+    keys: Arc<Keys>,
+    sessions: Sessions,
+    enumeration_protection: bool,
 }
 
 impl AuthService {
-    pub fn new(user_service: UserService) -> Self {
-        Self { user_service }
+    pub fn new(user_service: UserService, keys: Keys) -> Self {
+        Self {
+            user_service,
+            keys: Arc::new(keys),
+            sessions: Sessions::new(SessionSettings {
+                cookies: false,
+                secure: true,
+                same_site: SameSite::Lax,
+            }),
+            enumeration_protection: false,
+        }
+    }
+
+    pub fn keys(&self) -> Arc<Keys> {
+        self.keys.clone()
+    }
+
+    /// Bearer tokens in response bodies unless cookie sessions are enabled.
+    pub fn with_sessions(mut self, settings: SessionSettings) -> Self {
+        self.sessions = Sessions::new(settings);
+        self
+    }
+
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
+    }
+
+    /// When enabled, failed logins are indistinguishable in status, body and
+    /// timing whether the account is unknown, password-less or the password
+    /// is wrong.
+    pub fn with_enumeration_protection(mut self, enabled: bool) -> Self {
+        if enabled {
+            Lazy::force(&DUMMY_PASSWORD_HASH);
+        }
+        self.enumeration_protection = enabled;
+        self
     }
 
     pub async fn login_with_username(
@@ -26,23 +85,61 @@ impl AuthService {
         let user = self
             .user_service
             .find_by_username(&login_request.username)
-            .await?;
+            .await;
 
-        self.verify_password_and_generate_token(user, login_request.password)
-            .await
+        let result = self.authenticate(user, login_request.password).await;
+        metrics::record_login("password", login_outcome(&result));
+        result
     }
 
     pub async fn login_with_email(
         &self,
         login_request: EmailLoginRequest,
     ) -> Result<LoginResponse, AuthError> {
-        let user = self
-            .user_service
-            .find_by_email(&login_request.email)
-            .await?;
+        let user = self.user_service.find_by_email(&login_request.email).await;
 
-        self.verify_password_and_generate_token(user, login_request.password)
-            .await
+        let result = self.authenticate(user, login_request.password).await;
+        metrics::record_login("password", login_outcome(&result));
+        result
+    }
+
+    async fn authenticate(
+        &self,
+        user: Result<User, UserError>,
+        password: String,
+    ) -> Result<LoginResponse, AuthError> {
+        if !self.enumeration_protection {
+            let user = user.map_err(|e| match e {
+                UserError::NotFound => AuthError::UnknownAccount,
+                e => e.into(),
+            })?;
+            return self
+                .verify_password_and_generate_token(user, password)
+                .await;
+        }
+
+        let user = match user {
+            Ok(user) => Some(user),
+            Err(UserError::NotFound) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        match user {
+            Some(user) if verify_credentials(Some(&user), &password) => {
+                let token = self.generate_token(user.id)?;
+                Ok(LoginResponse::new(token))
+            }
+            Some(_) => Err(AuthError::InvalidCredentials(
+                UNIFORM_LOGIN_FAILURE.to_string(),
+            )),
+            None => {
+                // Unknown accounts still pay for one verification
+                verify_credentials(None, &password);
+                Err(AuthError::InvalidCredentials(
+                    UNIFORM_LOGIN_FAILURE.to_string(),
+                ))
+            }
+        }
     }
 
     async fn verify_password_and_generate_token(
@@ -67,7 +164,7 @@ impl AuthService {
 
     pub fn generate_token(&self, user_id: i32) -> Result<String, AuthError> {
         let claims = Claims::new(user_id);
-        encode(&Header::default(), &claims, &KEYS.encoding).map_err(AuthError::TokenCreation)
+        encode(&Header::default(), &claims, &self.keys.encoding).map_err(AuthError::TokenCreation)
     }
 
     pub async fn get_user(&self, user_id: i32) -> Result<User, AuthError> {
@@ -87,8 +184,75 @@ impl AuthService {
     }
 }
 
+/// Classifies a login attempt for `auth_logins_total`.
+pub(crate) fn login_outcome<T>(result: &Result<T, AuthError>) -> LoginOutcome {
+    match result {
+        Ok(_) => LoginOutcome::Success,
+        Err(AuthError::InvalidCredentials(_) | AuthError::UnknownAccount) => {
+            LoginOutcome::InvalidCredentials
+        }
+        Err(_) => LoginOutcome::Error,
+    }
+}
+
+/// Runs exactly one Argon2 verification regardless of whether `user` exists
+/// or has a password, falling back to a dummy hash.
+fn verify_credentials(user: Option<&User>, password: &str) -> bool {
+    let candidate = user.filter(|user| user.google_id.is_none());
+    let stored_hash = candidate
+        .map(|user| user.password_hash.as_str())
+        .unwrap_or(DUMMY_PASSWORD_HASH.as_str());
+
+    let parsed_hash = PasswordHash::new(stored_hash)
+        .or_else(|_| PasswordHash::new(DUMMY_PASSWORD_HASH.as_str()))
+        .expect("Dummy password hash is valid");
+
+    let verified = Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok();
+
+    candidate.is_some() && verified
+}
+
 impl From<argon2::password_hash::Error> for AuthError {
     fn from(_: argon2::password_hash::Error) -> Self {
         AuthError::InvalidCredentials("Invalid password".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn user(password: &str, google_id: Option<&str>) -> User {
+        let salt = SaltString::generate(&mut OsRng);
+        User {
+            id: 1,
+            username: "bob".to_string(),
+            email: "bob@example.com".to_string(),
+            password_hash: Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .unwrap()
+                .to_string(),
+            google_id: google_id.map(str::to_string),
+            avatar_url: None,
+            created_at: Utc::now().naive_utc(),
+            version: 1,
+            updated_at: Utc::now().naive_utc(),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_credentials() {
+        let password_user = user("correct horse", None);
+        let google_user = user("correct horse", Some("google-123"));
+
+        assert!(verify_credentials(Some(&password_user), "correct horse"));
+        assert!(!verify_credentials(Some(&password_user), "wrong"));
+        assert!(!verify_credentials(Some(&google_user), "correct horse"));
+        assert!(!verify_credentials(None, "correct horse"));
+        assert!(!verify_credentials(None, "queso-dummy-password"));
+    }
+}
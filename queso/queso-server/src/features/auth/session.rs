@@ -0,0 +1,317 @@
+use axum::{
+    Json,
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::RngCore;
+
+use super::model::{AuthError, LoginResponse, TOKEN_LIFETIME};
+use crate::{api::ApiError, config::settings::SessionSettings};
+
+/// `HttpOnly` cookie carrying the JWT.
+pub const SESSION_COOKIE: &str = "queso_session";
+/// Script-readable cookie the SPA echoes back in [`CSRF_HEADER`].
+pub const CSRF_COOKIE: &str = "queso_csrf";
+pub static CSRF_HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+
+/// Where a request's token came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Credential<'a> {
+    Bearer(&'a str),
+    Cookie(&'a str),
+}
+
+impl<'a> Credential<'a> {
+    pub fn token(&self) -> &'a str {
+        match self {
+            Credential::Bearer(token) | Credential::Cookie(token) => token,
+        }
+    }
+}
+
+/// The request's token, preferring the `Authorization` header over the
+/// session cookie.
+pub fn credential(headers: &HeaderMap) -> Option<Credential<'_>> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let (scheme, token) = value.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        })
+        .filter(|token| !token.is_empty());
+
+    bearer
+        .map(Credential::Bearer)
+        .or_else(|| cookie(headers, SESSION_COOKIE).map(Credential::Cookie))
+}
+
+/// The value of cookie `name` from the `Cookie` headers.
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// Double-submit check for cookie-authenticated requests: anything but
+/// `GET`, `HEAD` and `OPTIONS` must send the CSRF cookie's value in
+/// `X-CSRF-Token`, which another site cannot read.
+pub fn check_csrf(method: &Method, headers: &HeaderMap) -> Result<(), AuthError> {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(());
+    }
+
+    let expected = cookie(headers, CSRF_COOKIE).ok_or(AuthError::CsrfMismatch)?;
+    let submitted = headers
+        .get(&CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AuthError::CsrfMismatch)?;
+
+    if constant_time_eq(expected.as_bytes(), submitted.as_bytes()) {
+        Ok(())
+    } else {
+        Err(AuthError::CsrfMismatch)
+    }
+}
+
+/// Applies [`check_csrf`] to every request authenticated by the session
+/// cookie, so routes that read the session some other way than through
+/// `AuthUser`, or not at all, are covered too.
+pub async fn csrf(req: Request, next: Next) -> Response {
+    if let Some(Credential::Cookie(_)) = credential(req.headers())
+        && let Err(e) = check_csrf(req.method(), req.headers())
+    {
+        return ApiError::from(e).into_response();
+    }
+    next.run(req).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn csrf_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Turns logins and logouts into responses for the configured session mode.
+#[derive(Debug, Clone)]
+pub struct Sessions {
+    settings: SessionSettings,
+}
+
+impl Sessions {
+    pub fn new(settings: SessionSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Bearer mode returns the token in the body. Cookie mode sets it as an
+    /// `HttpOnly` cookie alongside a fresh CSRF cookie and leaves it out of
+    /// the body.
+    pub fn login(&self, login: LoginResponse) -> Response {
+        let token = match login.token {
+            Some(token) if self.settings.cookies => token,
+            _ => return Json(login).into_response(),
+        };
+        let max_age = TOKEN_LIFETIME.num_seconds();
+        let mut response = Json(LoginResponse { token: None }).into_response();
+        self.set_cookie(&mut response, SESSION_COOKIE, &token, true, max_age);
+        self.set_cookie(&mut response, CSRF_COOKIE, &csrf_token(), false, max_age);
+        response
+    }
+
+    /// Expires the session cookies; a no-op in bearer mode.
+    pub fn logout(&self) -> Response {
+        let mut response = ().into_response();
+        if self.settings.cookies {
+            self.set_cookie(&mut response, SESSION_COOKIE, "", true, 0);
+            self.set_cookie(&mut response, CSRF_COOKIE, "", false, 0);
+        }
+        response
+    }
+
+    fn set_cookie(
+        &self,
+        response: &mut Response,
+        name: &str,
+        value: &str,
+        http_only: bool,
+        max_age: i64,
+    ) {
+        let mut cookie = format!(
+            "{}={}; Path=/; Max-Age={}; SameSite={}",
+            name,
+            value,
+            max_age,
+            self.settings.same_site.as_str()
+        );
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if self.settings.secure {
+            cookie.push_str("; Secure");
+        }
+
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::settings::SameSite,
+        features::auth::model::{AuthUser, Claims, Keys},
+    };
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+    };
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn sessions(cookies: bool) -> Sessions {
+        Sessions::new(SessionSettings {
+            cookies,
+            secure: true,
+            same_site: SameSite::Strict,
+        })
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn set_cookies(response: &Response) -> Vec<String> {
+        response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_cookie_mode_keeps_the_token_out_of_the_body() {
+        let response = sessions(true).login(LoginResponse::new("jwt".into()));
+        let cookies = set_cookies(&response);
+
+        assert_eq!(
+            cookies[0],
+            "queso_session=jwt; Path=/; Max-Age=86400; SameSite=Strict; HttpOnly; Secure"
+        );
+        assert!(cookies[1].starts_with("queso_csrf="));
+        assert!(!cookies[1].contains("HttpOnly"));
+        assert_eq!(body(response).await, "{}");
+
+        let response = sessions(false).login(LoginResponse::new("jwt".into()));
+        assert!(set_cookies(&response).is_empty());
+        assert_eq!(body(response).await, r#"{"token":"jwt"}"#);
+
+        let cookies = set_cookies(&sessions(true).logout());
+        assert!(cookies.iter().all(|cookie| cookie.contains("Max-Age=0")));
+    }
+
+    fn app(keys: Arc<Keys>) -> Router {
+        let whoami = |user: AuthUser| async move { user.user_id.to_string() };
+        Router::new()
+            .route("/me", get(whoami).post(whoami))
+            // Acts on the session without extracting `AuthUser`
+            .route("/unauthenticated", post(|| async { "changed" }))
+            .layer(axum::middleware::from_fn(csrf))
+            .with_state(keys)
+    }
+
+    #[tokio::test]
+    async fn test_cookie_requests_need_a_matching_csrf_token() {
+        let keys = Arc::new(Keys::new(b"session-test-secret"));
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &Claims::new(7),
+            &keys.encoding,
+        )
+        .unwrap();
+        let session = format!("{}={}; {}=csrf-value", SESSION_COOKIE, token, CSRF_COOKIE);
+        let send_to = |uri: &str, method: Method, csrf: Option<&str>, auth: Option<String>| {
+            let mut request = Request::builder().method(method).uri(uri);
+            match auth {
+                Some(auth) => request = request.header(header::AUTHORIZATION, auth),
+                None => request = request.header(header::COOKIE, session.as_str()),
+            }
+            if let Some(csrf) = csrf {
+                request = request.header(&CSRF_HEADER, csrf);
+            }
+            app(keys.clone()).oneshot(request.body(Body::empty()).unwrap())
+        };
+        let send = |method: Method, csrf: Option<&str>, auth: Option<String>| {
+            send_to("/me", method, csrf, auth)
+        };
+
+        let response = send(Method::GET, None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, "7");
+
+        for csrf in [None, Some("wrong-value")] {
+            let response = send(Method::POST, csrf, None).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert!(body(response).await.contains("csrf_token_mismatch"));
+        }
+
+        let response = send(Method::POST, Some("csrf-value"), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send_to("/unauthenticated", Method::POST, None, None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send_to("/unauthenticated", Method::POST, Some("csrf-value"), None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Bearer tokens are not sent automatically, so need no CSRF token
+        let bearer = Some(format!("Bearer {}", token));
+        let response = send(Method::POST, None, bearer).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_credential_prefers_the_authorization_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; queso_session=from-cookie"),
+        );
+        assert_eq!(
+            credential(&headers),
+            Some(Credential::Cookie("from-cookie"))
+        );
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("bearer from-header"),
+        );
+        assert_eq!(
+            credential(&headers),
+            Some(Credential::Bearer("from-header"))
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use diesel::{RunQueryDsl, sql_query};
+use diesel_migrations::MigrationHarness;
+
+use crate::config::database::{DbPool, MIGRATIONS, with_connection};
+
+/// A dependency the server needs in order to serve traffic.
+///
+/// `check` returns a short, client-safe reason on failure; details belong in
+/// the logs.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// A round trip through the connection pool.
+pub struct DatabaseCheck {
+    pool: DbPool,
+}
+
+impl DatabaseCheck {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for DatabaseCheck {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        with_connection(&self.pool, |conn| sql_query("SELECT 1").execute(conn))
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                tracing::warn!("Database health check failed: {}", e);
+                "database unreachable".to_string()
+            })
+    }
+}
+
+/// Fails while the schema is behind the migrations compiled into the binary.
+pub struct MigrationsCheck {
+    pool: DbPool,
+}
+
+impl MigrationsCheck {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for MigrationsCheck {
+    fn name(&self) -> &'static str {
+        "migrations"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let pending = with_connection(&self.pool, |conn| {
+            Ok(conn
+                .pending_migrations(MIGRATIONS)
+                .map(|pending| pending.len())
+                .map_err(|e| e.to_string()))
+        })
+        .await;
+
+        match pending {
+            Ok(Ok(0)) => Ok(()),
+            Ok(Ok(count)) => Err(format!("{} pending migration(s)", count)),
+            Ok(Err(e)) => {
+                tracing::warn!("Migration health check failed: {}", e);
+                Err("unable to read migration status".to_string())
+            }
+            Err(e) => {
+                tracing::warn!("Migration health check failed: {}", e);
+                Err("database unreachable".to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::database::PoolSettings, test_support::TestDatabase};
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_checks_pass_against_migrated_database() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+
+        assert_eq!(DatabaseCheck::new(db.pool.clone()).check().await, Ok(()));
+        assert_eq!(MigrationsCheck::new(db.pool.clone()).check().await, Ok(()));
+
+        db.stop().await;
+
+        assert!(DatabaseCheck::new(db.pool.clone()).check().await.is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use axum::{Json, extract::State, http::StatusCode};
+
+use super::{
+    model::{HealthResponse, HealthStatus},
+    service::HealthService,
+};
+
+/// Liveness probe
+///
+/// Succeeds while the process is able to serve requests at all; it does not
+/// look at dependencies, so orchestrators only restart genuinely stuck
+/// instances.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = 200, description = "The process is alive", body = HealthResponse)
+    ),
+    tag = "health"
+)]
+pub async fn live() -> Json<HealthResponse> {
+    Json(HealthResponse::ok())
+}
+
+/// Readiness probe
+///
+/// Checks a pooled database round trip, pending migrations and any other
+/// configured dependencies, and fails as soon as shutdown begins.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Ready to receive traffic", body = HealthResponse),
+        (status = 503, description = "A dependency is failing or the server is shutting down", body = HealthResponse)
+    ),
+    tag = "health"
+)]
+pub async fn ready(State(service): State<HealthService>) -> (StatusCode, Json<HealthResponse>) {
+    let response = service.readiness().await;
+    let status = match response.status {
+        HealthStatus::Ok => StatusCode::OK,
+        HealthStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(response))
+}
@@ -0,0 +1,9 @@
+pub mod checks;
+pub mod handler;
+pub mod model;
+pub mod router;
+pub mod service;
+
+pub use checks::HealthCheck;
+pub use router::health_routes;
+pub use service::HealthService;
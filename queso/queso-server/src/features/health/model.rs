@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Fail,
+}
+
+/// Outcome of a single readiness check.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckResult {
+    pub status: HealthStatus,
+    /// Time the check took, in milliseconds
+    #[schema(example = 3)]
+    pub latency_ms: u64,
+    /// Why the check failed; never contains internal details
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    /// Per-check results, keyed by check name; empty for liveness
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, CheckResult>,
+}
+
+impl HealthResponse {
+    pub fn ok() -> Self {
+        Self {
+            status: HealthStatus::Ok,
+            checks: BTreeMap::new(),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use axum::{Router, routing::get};
+
+use super::{handler, service::HealthService};
+
+pub fn health_routes() -> Router<HealthService> {
+    Router::new()
+        .route("/live", get(handler::live))
+        .route("/ready", get(handler::ready))
+        // Older probes still point at `/health`
+        .route("/", get(handler::ready))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shutdown::Shutdown;
+    use axum::{body::Body, extract::Request, http::StatusCode};
+    use tower::ServiceExt;
+
+    async fn status(app: &Router, uri: &str) -> StatusCode {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_liveness_ignores_readiness() {
+        let shutdown = Shutdown::new();
+        let app = Router::new()
+            .nest("/health", health_routes())
+            .with_state(HealthService::new(shutdown.clone()));
+
+        assert_eq!(status(&app, "/health/ready").await, StatusCode::OK);
+        assert_eq!(status(&app, "/health").await, StatusCode::OK);
+
+        shutdown.begin();
+
+        assert_eq!(status(&app, "/health/live").await, StatusCode::OK);
+        assert_eq!(
+            status(&app, "/health/ready").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status(&app, "/health").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
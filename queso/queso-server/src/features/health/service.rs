@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinSet;
+
+use super::{
+    checks::HealthCheck,
+    model::{CheckResult, HealthResponse, HealthStatus},
+};
+use crate::shutdown::Shutdown;
+
+/// Upper bound for a single check so a hung dependency can't hang the probe.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+pub struct HealthService {
+    checks: Vec<Arc<dyn HealthCheck>>,
+    shutdown: Shutdown,
+}
+
+impl HealthService {
+    pub fn new(shutdown: Shutdown) -> Self {
+        Self {
+            checks: Vec::new(),
+            shutdown,
+        }
+    }
+
+    pub fn with_check(mut self, check: impl HealthCheck + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Runs every check concurrently. Readiness fails if any check fails or
+    /// shutdown has begun.
+    pub async fn readiness(&self) -> HealthResponse {
+        let mut running = JoinSet::new();
+        for check in &self.checks {
+            let check = check.clone();
+            running.spawn(async move {
+                let started = Instant::now();
+                let outcome = tokio::time::timeout(CHECK_TIMEOUT, check.check())
+                    .await
+                    .unwrap_or_else(|_| Err("timed out".to_string()));
+                (check.name(), run_result(outcome, started.elapsed()))
+            });
+        }
+
+        let mut checks = BTreeMap::new();
+        while let Some(joined) = running.join_next().await {
+            let (name, result) = joined.unwrap_or_else(|e| {
+                tracing::error!("Health check panicked: {}", e);
+                (
+                    "unknown",
+                    run_result(Err("check panicked".into()), Duration::ZERO),
+                )
+            });
+            checks.insert(name.to_string(), result);
+        }
+
+        let healthy = !self.shutdown.is_shutting_down()
+            && checks
+                .values()
+                .all(|check| check.status == HealthStatus::Ok);
+
+        HealthResponse {
+            status: if healthy {
+                HealthStatus::Ok
+            } else {
+                HealthStatus::Fail
+            },
+            checks,
+        }
+    }
+}
+
+fn run_result(outcome: Result<(), String>, latency: Duration) -> CheckResult {
+    let latency_ms = latency.as_millis() as u64;
+    match outcome {
+        Ok(()) => CheckResult {
+            status: HealthStatus::Ok,
+            latency_ms,
+            error: None,
+        },
+        Err(error) => CheckResult {
+            status: HealthStatus::Fail,
+            latency_ms,
+            error: Some(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct Fixed(&'static str, Result<(), &'static str>);
+
+    #[async_trait]
+    impl HealthCheck for Fixed {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            self.1.map_err(str::to_string)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readiness_reports_each_check() {
+        let shutdown = Shutdown::new();
+        let service = HealthService::new(shutdown.clone())
+            .with_check(Fixed("database", Ok(())))
+            .with_check(Fixed("mailer", Err("unreachable")));
+
+        let response = service.readiness().await;
+
+        assert_eq!(response.status, HealthStatus::Fail);
+        assert_eq!(response.checks["database"].status, HealthStatus::Ok);
+        assert_eq!(response.checks["mailer"].status, HealthStatus::Fail);
+        assert_eq!(
+            response.checks["mailer"].error.as_deref(),
+            Some("unreachable")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readiness_fails_once_shutting_down() {
+        let shutdown = Shutdown::new();
+        let service = HealthService::new(shutdown.clone()).with_check(Fixed("database", Ok(())));
+        assert_eq!(service.readiness().await.status, HealthStatus::Ok);
+
+        shutdown.begin();
+
+        assert_eq!(service.readiness().await.status, HealthStatus::Fail);
+    }
+}
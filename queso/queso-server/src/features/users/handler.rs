@@ -1,143 +1,809 @@
 use axum::{
     Json,
-    extract::Path,
     extract::State,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
 
-#[derive(Deserialize, ToSchema)]
+use crate::{
+    api::{ApiError, IfMatch, IfNoneMatch, Path, ProblemDetails, ValidatedJson, conditional::etag},
+    features::auth::{AdminUser, AuthError, AuthUser},
+};
+
+use super::{
+    model::{NewUser, User, UserChanges, UserError},
+    service::UserService,
+};
+
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct CreateUserRequest {
+    #[validate(
+        length(min = 3, max = 32, message = "must be between 3 and 32 characters"),
+        custom(function = "validate_username")
+    )]
+    #[schema(min_length = 3, max_length = 32, pattern = "^[A-Za-z0-9_.-]+$")]
     pub username: String,
+    #[validate(
+        email(message = "must be a valid email address"),
+        length(max = 254, message = "must be at most 254 characters")
+    )]
+    #[schema(format = "email", max_length = 254)]
     pub email: String,
+    #[validate(length(min = 8, max = 128, message = "must be between 8 and 128 characters"))]
+    #[schema(min_length = 8, max_length = 128, format = "password")]
     pub password: String,
 }
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    error: String,
+/// Fields to change; omitted fields keep their value.
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct UpdateUserRequest {
+    #[validate(
+        length(min = 3, max = 32, message = "must be between 3 and 32 characters"),
+        custom(function = "validate_username")
+    )]
+    #[schema(min_length = 3, max_length = 32, pattern = "^[A-Za-z0-9_.-]+$")]
+    pub username: Option<String>,
+    #[validate(
+        email(message = "must be a valid email address"),
+        length(max = 254, message = "must be at most 254 characters")
+    )]
+    #[schema(format = "email", max_length = 254)]
+    pub email: Option<String>,
 }
 
-use super::{
-    model::{NewUser, User, UserError},
-    service::UserService,
-};
+impl From<UpdateUserRequest> for UserChanges {
+    fn from(request: UpdateUserRequest) -> Self {
+        Self {
+            username: request.username,
+            email: request.email,
+        }
+    }
+}
+
+/// Returned instead of the created user when enumeration protection is enabled.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SignupAcceptedResponse {
+    pub message: String,
+}
 
-impl IntoResponse for UserError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
+/// Usernames are restricted to ASCII letters, digits, `_`, `.` and `-`.
+fn validate_username(username: &str) -> Result<(), ValidationError> {
+    let valid = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("username_charset")
+            .with_message("may only contain letters, digits, '_', '.' and '-'".into()))
+    }
+}
+
+impl From<UserError> for ApiError {
+    fn from(error: UserError) -> Self {
+        match error {
+            UserError::NotFound => ApiError::not_found("user_not_found", "User not found"),
             UserError::UsernameExists => {
-                (StatusCode::CONFLICT, "Username already exists".to_string())
+                ApiError::conflict("username_exists", "Username already exists")
             }
-            UserError::EmailExists => (StatusCode::CONFLICT, "Email already exists".to_string()),
-            UserError::PasswordHashError(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Error processing password".to_string(),
-            ),
-            UserError::DatabaseError(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Database error: {}", e),
-            ),
-            UserError::OAuthError(msg) => (StatusCode::UNAUTHORIZED, msg),
-            UserError::InternalError => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error".to_string(),
+            UserError::EmailExists => ApiError::conflict("email_exists", "Email already exists"),
+            UserError::VersionMismatch => ApiError::new(
+                StatusCode::PRECONDITION_FAILED,
+                "precondition_failed",
+                "The user was modified since it was read; fetch it again and retry",
             ),
-        };
-
-        let body = Json(ErrorResponse {
-            error: error_message,
-        });
-
-        (status, body).into_response()
+            UserError::PasswordHashError(e) => {
+                ApiError::internal("password_hash_error", "Error processing password")
+                    .with_internal(e)
+            }
+            UserError::DatabaseError(e) => {
+                ApiError::internal("database_error", "An internal error occurred").with_internal(e)
+            }
+            UserError::DatabaseUnavailable(reason) => ApiError::service_unavailable(
+                "database_unavailable",
+                "The service is temporarily unavailable, please retry shortly",
+            )
+            .with_internal(reason),
+            UserError::OAuthError(msg) => {
+                ApiError::unauthorized("oauth_error", "OAuth authentication failed")
+                    .with_internal(msg)
+            }
+            UserError::InternalError => {
+                ApiError::internal("internal_error", "Internal server error")
+            }
+        }
     }
 }
 
 /// Get a user by ID
+///
+/// The `ETag` identifies the user's current version; send it back in
+/// `If-Match` to update or delete the user.
 #[utoipa::path(
     get,
-    path = "/api/users/{id}",
+    path = "/users/{id}",
     responses(
-        (status = 200, description = "User found successfully", body = User),
-        (status = 404, description = "User not found")
+        (status = 200, description = "User found successfully", body = User,
+            headers(("ETag" = String, description = "Current version of the user"))),
+        (status = 304, description = "The user still matches If-None-Match"),
+        (status = 404, description = "User not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     ),
     params(
-        ("id" = i32, Path, description = "User ID")
+        ("id" = i32, Path, description = "User ID"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy")
     ),
     tag = "users"
 )]
 pub async fn get_user(
     State(service): State<UserService>,
     Path(id): Path<i32>,
-) -> Result<Json<User>, StatusCode> {
-    match service.get_user(id).await {
-        Ok(user) => Ok(Json(user)),
-        Err(_) => Err(StatusCode::NOT_FOUND),
+    if_none_match: IfNoneMatch,
+) -> Result<Response, ApiError> {
+    let user = service.get_user(id).await?;
+    let etag = etag(user.version);
+    if if_none_match.matches(user.version) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
     }
+    Ok(([(header::ETAG, etag)], Json(user)).into_response())
 }
 
 /// Get all users
 #[utoipa::path(
     get,
-    path = "/api/users",
+    path = "/users",
     responses(
-        (status = 200, description = "List all users", body = Vec<User>)
+        (status = 200, description = "List all users", body = Vec<User>),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     ),
     tag = "users"
 )]
-pub async fn get_users(State(service): State<UserService>) -> Result<Json<Vec<User>>, StatusCode> {
-    match service.get_users().await {
-        Ok(users) => Ok(Json(users)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+pub async fn get_users(State(service): State<UserService>) -> Result<Json<Vec<User>>, ApiError> {
+    let users = service.get_users().await?;
+    Ok(Json(users))
 }
 
 /// Create a new user
+///
+/// With enumeration protection enabled this always answers `202 Accepted`,
+/// whether or not the username or email was already taken; the outcome is
+/// sent to the submitted email address instead.
 #[utoipa::path(
     post,
-    path = "/api/users",
+    path = "/users",
     request_body = CreateUserRequest,
     responses(
         (status = 201, description = "User created successfully", body = User),
-        (status = 400, description = "Invalid user data"),
-        (status = 409, description = "User already exists")
+        (status = 202, description = "Sign-up accepted; the outcome is sent by email", body = SignupAcceptedResponse),
+        (status = 400, description = "Malformed request body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "User already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid user data", body = ProblemDetails, content_type = "application/problem+json")
     ),
     tag = "users"
 )]
 pub async fn create_user(
     State(service): State<UserService>,
-    Json(new_user_request): Json<CreateUserRequest>,
-) -> Result<(StatusCode, Json<User>), UserError> {
+    ValidatedJson(new_user_request): ValidatedJson<CreateUserRequest>,
+) -> Result<Response, ApiError> {
     let new_user = NewUser::from_request(
         new_user_request.username,
         new_user_request.email,
         new_user_request.password,
-    )?;
+    )
+    .map_err(UserError::from)?;
 
-    match service.create_user(new_user).await {
-        Ok(user) => Ok((StatusCode::CREATED, Json(user))),
-        Err(err) => Err(err),
+    if service.enumeration_protection() {
+        service.register_quietly(new_user).await?;
+        let body = SignupAcceptedResponse {
+            message: "Check your email to finish signing up".to_string(),
+        };
+        return Ok((StatusCode::ACCEPTED, Json(body)).into_response());
     }
+
+    let user = service.create_user(new_user).await?;
+    Ok((StatusCode::CREATED, Json(user)).into_response())
+}
+
+/// Update a user
+///
+/// Users may only update themselves. Only applies if the user is still at
+/// the version in `If-Match`, so changes made since it was read are not
+/// overwritten.
+#[utoipa::path(
+    patch,
+    path = "/users/{id}",
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "User updated successfully", body = User,
+            headers(("ETag" = String, description = "New version of the user"))),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Not the signed-in user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "User not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Username or email already taken", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The user was modified since it was read", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid user data", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "User ID"),
+        ("If-Match" = String, Header, description = "ETag of the version being updated")
+    ),
+    security(
+        ("jwt" = []),
+        ("session" = [])
+    ),
+    tag = "users"
+)]
+pub async fn update_user(
+    State(service): State<UserService>,
+    auth_user: AuthUser,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+    ValidatedJson(request): ValidatedJson<UpdateUserRequest>,
+) -> Result<Response, ApiError> {
+    if auth_user.user_id != id {
+        return Err(AuthError::Forbidden.into());
+    }
+    let user = service
+        .update_user(id, request.into(), if_match.versions())
+        .await?;
+    Ok(([(header::ETAG, etag(user.version))], Json(user)).into_response())
 }
 
 /// Delete a user
+///
+/// The user can be restored through the admin endpoints until the retention
+/// period ends, after which it is purged.
 #[utoipa::path(
     delete,
-    path = "/api/users/{id}",
+    path = "/users/{id}",
     responses(
         (status = 204, description = "User deleted successfully"),
-        (status = 404, description = "User not found")
+        (status = 404, description = "User not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The user was modified since it was read", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     ),
     params(
-        ("id" = i32, Path, description = "User ID")
+        ("id" = i32, Path, description = "User ID"),
+        ("If-Match" = String, Header, description = "ETag of the version being deleted")
     ),
     tag = "users"
 )]
-pub async fn delete_user(State(service): State<UserService>, Path(id): Path<i32>) -> StatusCode {
-    match service.delete_user(id).await {
-        Ok(_) => StatusCode::NO_CONTENT,
-        Err(_) => StatusCode::NOT_FOUND,
+pub async fn delete_user(
+    State(service): State<UserService>,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> Result<StatusCode, ApiError> {
+    service.delete_user(id, if_match.versions()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List deleted users
+///
+/// Users deleted within the retention period, most recently deleted first.
+#[utoipa::path(
+    get,
+    path = "/admin/users/deleted",
+    responses(
+        (status = 200, description = "Users that can still be restored", body = Vec<User>),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Not an admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(
+        ("jwt" = []),
+        ("session" = [])
+    ),
+    tag = "admin"
+)]
+pub async fn get_deleted_users(
+    State(service): State<UserService>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<User>>, ApiError> {
+    let users = service.get_deleted_users().await?;
+    Ok(Json(users))
+}
+
+/// Restore a deleted user
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/restore",
+    responses(
+        (status = 200, description = "User restored successfully", body = User,
+            headers(("ETag" = String, description = "Current version of the user"))),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Not an admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No user deleted within the retention period", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Username or email taken since the deletion", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "User ID")
+    ),
+    security(
+        ("jwt" = []),
+        ("session" = [])
+    ),
+    tag = "admin"
+)]
+pub async fn restore_user(
+    State(service): State<UserService>,
+    _admin: AdminUser,
+    Path(id): Path<i32>,
+) -> Result<Response, ApiError> {
+    let user = service.restore_user(id).await?;
+    Ok(([(header::ETAG, etag(user.version))], Json(user)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::request_id::REQUEST_ID_HEADER,
+        config::database::PoolSettings,
+        features::{
+            auth::model::{Admins, Claims, Keys},
+            users::{UserRepository, UsersState, admin_user_routes, user_routes},
+        },
+        server,
+        shutdown::Shutdown,
+        test_support::{self, TestDatabase},
+    };
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, header},
+    };
+    use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
+    use std::{sync::Arc, time::Duration};
+    use tower::ServiceExt;
+
+    const SECRET: &[u8] = b"users-test-secret";
+    const ADMIN: i32 = 99;
+
+    fn state(user_service: UserService) -> UsersState {
+        UsersState {
+            user_service,
+            keys: Arc::new(Keys::new(SECRET)),
+            admins: Admins::new([ADMIN]),
+        }
+    }
+
+    /// An `Authorization` header for `user_id`, signed with the test keys.
+    fn bearer(user_id: i32) -> String {
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &Claims::new(user_id),
+            &Keys::new(SECRET).encoding,
+        )
+        .unwrap();
+        format!("Bearer {}", token)
+    }
+
+    struct ConstraintViolation;
+
+    impl DatabaseErrorInformation for ConstraintViolation {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint \"users_email_key\""
+        }
+        fn details(&self) -> Option<&str> {
+            Some("Key (email)=(bob@example.com) already exists.")
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            Some("users")
+        }
+        fn column_name(&self) -> Option<&str> {
+            Some("email")
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            Some("users_email_key")
+        }
+        fn statement_position(&self) -> Option<i32> {
+            None
+        }
+    }
+
+    fn database_errors() -> Vec<DieselError> {
+        vec![
+            DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new(ConstraintViolation),
+            ),
+            DieselError::DatabaseError(
+                DatabaseErrorKind::Unknown,
+                Box::new("relation \"users\" does not exist".to_string()),
+            ),
+            DieselError::QueryBuilderError(
+                "SELECT users.id FROM users WHERE users.email = $1".into(),
+            ),
+            DieselError::DeserializationError("column \"password_hash\" is null".into()),
+        ]
+    }
+
+    async fn body_of(error: ApiError) -> (StatusCode, String) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    fn assert_sanitized(body: &str) {
+        for leak in [
+            "SELECT",
+            "users_email_key",
+            "violates",
+            "relation",
+            "password_hash",
+            "bob@example.com",
+            "$1",
+        ] {
+            assert!(!body.contains(leak), "response leaked {:?}: {}", leak, body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_errors_are_not_exposed() {
+        for error in database_errors() {
+            let (status, body) = body_of(UserError::from(error).into()).await;
+
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(body.contains("database_error"));
+            assert_sanitized(&body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_errors_are_not_exposed_through_auth() {
+        for error in database_errors() {
+            let auth_error = AuthError::UserError(UserError::from(error));
+            let (status, body) = body_of(auth_error.into()).await;
+
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert_sanitized(&body);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_database_outage_returns_service_unavailable() {
+        let settings = PoolSettings {
+            connection_timeout: Duration::from_secs(1),
+            ..PoolSettings::default()
+        };
+        let db = TestDatabase::start(settings).await;
+        let app = user_routes().with_state(state(UserService::new(UserRepository::new(
+            db.pool.clone(),
+        ))));
+        let list_users = || Request::get("/").body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(list_users()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        db.stop().await;
+
+        // Every request during the outage fails fast instead of taking the
+        // task down, and the router keeps serving afterwards
+        for _ in 0..3 {
+            let response = app.clone().oneshot(list_users()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+            let (_, body) = response.into_parts();
+            let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body["code"], "database_unavailable");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_unavailable_maps_to_503() {
+        let error = UserError::DatabaseUnavailable("timed out waiting for connection".into());
+        let response = ApiError::from(error).into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "5");
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.contains("database_unavailable"));
+        assert!(!body.contains("timed out"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_hardened_sign_up_does_not_reveal_existing_accounts() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let mut settings = test_support::settings();
+        settings.auth.hardened_mode = true;
+        let app = server::app(&settings, db.pool.clone(), &Shutdown::new());
+        let sign_up = |username: &str, email: &str| {
+            let request = Request::post("/api/v1/users")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(&REQUEST_ID_HEADER, "sign-up-test")
+                .body(Body::from(format!(
+                    r#"{{"username":"{}","email":"{}","password":"correct horse battery"}}"#,
+                    username, email
+                )))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, body)
+            }
+        };
+
+        let fresh = sign_up("ana", "ana@example.com").await;
+        assert_eq!(fresh.0, StatusCode::ACCEPTED);
+        assert_eq!(sign_up("ana.b", "ana@example.com").await, fresh);
+        assert_eq!(sign_up("ana", "other@example.com").await, fresh);
+    }
+
+    async fn problem_code(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        body["code"].clone()
+    }
+
+    /// Renames user 1, signed in as `user_id`.
+    fn rename_as(user_id: Option<i32>, if_match: &str, username: &str) -> Request<Body> {
+        let mut request = Request::patch("/1")
+            .header(header::IF_MATCH, if_match)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(user_id) = user_id {
+            request = request.header(header::AUTHORIZATION, bearer(user_id));
+        }
+        request
+            .body(Body::from(format!(r#"{{"username":"{}"}}"#, username)))
+            .unwrap()
+    }
+
+    fn rename(if_match: &str, username: &str) -> Request<Body> {
+        rename_as(Some(1), if_match, username)
+    }
+
+    #[tokio::test]
+    async fn test_users_can_only_update_themselves() {
+        let app = user_routes().with_state(state(UserService::new(UserRepository::new(
+            test_support::offline_pool(),
+        ))));
+
+        let response = app
+            .clone()
+            .oneshot(rename_as(None, "\"1\"", "ana.b"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(problem_code(response).await, "missing_credentials");
+
+        let response = app
+            .oneshot(rename_as(Some(2), "\"1\"", "ana.b"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(problem_code(response).await, "forbidden");
+    }
+
+    #[tokio::test]
+    async fn test_changes_without_if_match_are_rejected() {
+        let app = user_routes().with_state(state(UserService::new(UserRepository::new(
+            test_support::offline_pool(),
+        ))));
+        let requests = [
+            Request::patch("/1")
+                .header(header::AUTHORIZATION, bearer(1))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"username":"ana"}"#))
+                .unwrap(),
+            Request::delete("/1").body(Body::empty()).unwrap(),
+        ];
+
+        for request in requests {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+            assert_eq!(problem_code(response).await, "precondition_required");
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_stale_etags_are_rejected() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let service = UserService::new(UserRepository::new(db.pool.clone()));
+        let new_user = NewUser::from_request(
+            "ana".to_string(),
+            "ana@example.com".to_string(),
+            "correct horse battery".to_string(),
+        )
+        .unwrap();
+        assert_eq!(service.create_user(new_user).await.unwrap().id, 1);
+        let app = user_routes().with_state(state(service));
+        let get = |if_none_match: &str| {
+            Request::get("/1")
+                .header(header::IF_NONE_MATCH, if_none_match)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let delete = |if_match: &str| {
+            Request::delete("/1")
+                .header(header::IF_MATCH, if_match)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(get("\"0\"")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"1\"");
+
+        let response = app.clone().oneshot(get("\"1\"")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], "\"1\"");
+
+        let response = app.clone().oneshot(rename("\"1\"", "ana.b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"2\"");
+
+        // A second admin still holding version 1
+        let response = app.clone().oneshot(rename("\"1\"", "ana.c")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(problem_code(response).await, "precondition_failed");
+        let response = app.clone().oneshot(delete("\"1\"")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        let response = app.clone().oneshot(get("\"1\"")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(delete("\"2\"")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = app.oneshot(delete("*")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints_require_an_admin() {
+        let app = admin_user_routes().with_state(state(UserService::new(UserRepository::new(
+            test_support::offline_pool(),
+        ))));
+        let requests = |authorization: Option<String>| {
+            [Request::get("/deleted"), Request::post("/1/restore")]
+                .map(|request| match &authorization {
+                    Some(authorization) => request.header(header::AUTHORIZATION, authorization),
+                    None => request,
+                })
+                .map(|request| request.body(Body::empty()).unwrap())
+        };
+
+        for request in requests(None) {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        for request in requests(Some(bearer(1))) {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert_eq!(problem_code(response).await, "forbidden");
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_deleted_users_can_be_restored_until_purged() {
+        let db = TestDatabase::start(PoolSettings::default()).await;
+        let service = UserService::new(UserRepository::new(db.pool.clone()));
+        for name in ["ana", "bob"] {
+            let new_user = NewUser::from_request(
+                name.to_string(),
+                format!("{}@example.com", name),
+                "correct horse battery".to_string(),
+            )
+            .unwrap();
+            service.create_user(new_user).await.unwrap();
+        }
+        let app = |service: &UserService| {
+            Router::new()
+                .nest("/users", user_routes())
+                .nest("/admin/users", admin_user_routes())
+                .with_state(state(service.clone()))
+        };
+        let send = |app: Router, request: Request<Body>| async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+            (status, body)
+        };
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        let as_admin = |request: axum::http::request::Builder| {
+            request
+                .header(header::AUTHORIZATION, bearer(ADMIN))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let restore = || as_admin(Request::post("/admin/users/1/restore"));
+
+        let delete = Request::delete("/users/1")
+            .header(header::IF_MATCH, "\"1\"")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(app(&service), delete).await.0, StatusCode::NO_CONTENT);
+
+        // Gone from the regular endpoints and sign-in lookups
+        assert_eq!(
+            send(app(&service), get("/users/1")).await.0,
+            StatusCode::NOT_FOUND
+        );
+        let (_, users) = send(app(&service), get("/users")).await;
+        assert_eq!(users.as_array().unwrap().len(), 1);
+        assert!(matches!(
+            service.find_by_email("ana@example.com").await,
+            Err(UserError::NotFound)
+        ));
+
+        let (status, deleted) = send(
+            app(&service),
+            as_admin(Request::get("/admin/users/deleted")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(deleted.as_array().unwrap().len(), 1);
+        assert_eq!(deleted[0]["username"], "ana");
+        assert!(deleted[0]["deleted_at"].is_string());
+
+        // The email is free again, until the user is restored
+        let new_user = NewUser::from_request(
+            "ana.new".to_string(),
+            "ANA@example.com".to_string(),
+            "correct horse battery".to_string(),
+        )
+        .unwrap();
+        let taken_by = service.create_user(new_user).await.unwrap();
+        let (status, body) = send(app(&service), restore()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "email_exists");
+        service.delete_user(taken_by.id, None).await.unwrap();
+
+        let (status, user) = send(app(&service), restore()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["version"], 3);
+        assert!(user.get("deleted_at").is_none());
+        assert_eq!(send(app(&service), get("/users/1")).await.0, StatusCode::OK);
+        let (status, body) = send(app(&service), restore()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "user_not_found");
+
+        // Past the retention period deleted users are no longer listed or
+        // restorable, and the purge removes them for good
+        service.delete_user(1, None).await.unwrap();
+        let expired = service.clone().with_deleted_retention(Duration::ZERO);
+        let (_, deleted) = send(
+            app(&expired),
+            as_admin(Request::get("/admin/users/deleted")),
+        )
+        .await;
+        assert_eq!(deleted, serde_json::json!([]));
+        let (status, body) = send(app(&expired), restore()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "user_not_found");
+        assert_eq!(service.purge_deleted_users().await.unwrap(), 0);
+        assert_eq!(expired.purge_deleted_users().await.unwrap(), 2);
+        assert_eq!(service.get_users().await.unwrap().len(), 1);
     }
 }
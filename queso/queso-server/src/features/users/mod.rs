@@ -6,5 +6,5 @@ pub mod service;
 
 pub use model::{NewUser, User};
 pub use repository::UserRepository;
-pub use router::user_routes;
+pub use router::{UsersState, admin_user_routes, user_routes};
 pub use service::UserService;
@@ -8,22 +8,63 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
 
+use crate::config::database::DbError;
+
 #[derive(Error, Debug)]
 pub enum UserError {
+    #[error("User not found")]
+    NotFound,
     #[error("Username already exists")]
     UsernameExists,
     #[error("Email already exists")]
     EmailExists,
+    /// The user changed since the version the caller expected
+    #[error("User was modified")]
+    VersionMismatch,
     #[error("Password hashing error: {0}")]
     PasswordHashError(#[from] argon2::password_hash::Error),
     #[error("Database error: {0}")]
-    DatabaseError(#[from] diesel::result::Error),
+    DatabaseError(diesel::result::Error),
+    #[error("Database unavailable: {0}")]
+    DatabaseUnavailable(String),
     #[error("OAuth error: {0}")]
     OAuthError(String),
     #[error("Internal server error")]
     InternalError,
 }
 
+/// Unique index on `lower(username)`, see the `case_insensitive_user_uniqueness` migration.
+pub const USERNAME_UNIQUE_INDEX: &str = "users_username_lower_key";
+/// Unique index on `lower(email)`, see the `case_insensitive_user_uniqueness` migration.
+pub const EMAIL_UNIQUE_INDEX: &str = "users_email_lower_key";
+
+impl From<diesel::result::Error> for UserError {
+    fn from(error: diesel::result::Error) -> Self {
+        use diesel::result::{DatabaseErrorKind, Error};
+
+        match error {
+            Error::NotFound => UserError::NotFound,
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) => {
+                match info.constraint_name() {
+                    Some(USERNAME_UNIQUE_INDEX) => UserError::UsernameExists,
+                    Some(EMAIL_UNIQUE_INDEX) => UserError::EmailExists,
+                    _ => UserError::DatabaseError(error),
+                }
+            }
+            error => UserError::DatabaseError(error),
+        }
+    }
+}
+
+impl From<DbError> for UserError {
+    fn from(error: DbError) -> Self {
+        match error {
+            DbError::Unavailable(reason) => UserError::DatabaseUnavailable(reason),
+            DbError::Query(error) => UserError::from(error),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, ToSchema)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -36,6 +77,12 @@ pub struct User {
     pub google_id: Option<String>,
     pub avatar_url: Option<String>,
     pub created_at: NaiveDateTime,
+    /// Incremented by every update; the user's `ETag` is derived from it
+    pub version: i32,
+    pub updated_at: NaiveDateTime,
+    /// Set while the user is deleted but can still be restored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable, ToSchema)]
@@ -48,6 +95,14 @@ pub struct NewUser {
     pub avatar_url: Option<String>,
 }
 
+/// Fields an update may change; `None` leaves a field as it is.
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = crate::schema::users)]
+pub struct UserChanges {
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
 impl NewUser {
     pub fn from_request(
         username: String,
@@ -106,3 +161,66 @@ pub struct GoogleUser {
     pub picture: String,
     pub locale: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
+
+    struct UniqueViolation(&'static str);
+
+    impl DatabaseErrorInformation for UniqueViolation {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint"
+        }
+        fn details(&self) -> Option<&str> {
+            None
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            Some("users")
+        }
+        fn column_name(&self) -> Option<&str> {
+            None
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            Some(self.0)
+        }
+        fn statement_position(&self) -> Option<i32> {
+            None
+        }
+    }
+
+    fn unique_violation(constraint: &'static str) -> UserError {
+        UserError::from(DieselError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new(UniqueViolation(constraint)),
+        ))
+    }
+
+    #[test]
+    fn test_unique_violations_map_to_conflicts() {
+        assert!(matches!(
+            unique_violation(USERNAME_UNIQUE_INDEX),
+            UserError::UsernameExists
+        ));
+        assert!(matches!(
+            unique_violation(EMAIL_UNIQUE_INDEX),
+            UserError::EmailExists
+        ));
+        assert!(matches!(
+            unique_violation("users_pkey"),
+            UserError::DatabaseError(_)
+        ));
+    }
+
+    #[test]
+    fn test_not_found_maps_to_not_found() {
+        assert!(matches!(
+            UserError::from(DieselError::NotFound),
+            UserError::NotFound
+        ));
+    }
+}
@@ -1,9 +1,18 @@
 use crate::{
-    config::database::DbPool,
-    features::users::model::{NewUser, User},
+    config::database::{DbError, DbPool, with_connection},
+    features::users::model::{NewUser, User, UserChanges},
     schema::users,
 };
-use diesel::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::{dsl::now, pg::data_types::PgInterval, prelude::*, sql_types::Text};
+use std::time::Duration;
+
+// Matches the `lower(...)` unique indexes so lookups are case-insensitive
+define_sql_function!(fn lower(x: Text) -> Text);
+
+fn interval(duration: Duration) -> PgInterval {
+    PgInterval::from_microseconds(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX))
+}
 
 #[derive(Clone)]
 pub struct UserRepository {
@@ -15,60 +24,238 @@ impl UserRepository {
         Self { pool }
     }
 
-    pub fn create(&self, new_user: &NewUser) -> Result<User, diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-
-        diesel::insert_into(users::table)
-            .values(new_user)
-            .returning(User::as_returning())
-            .get_result(&mut conn)
-            .map_err(|e| {
-                tracing::error!("Database error creating user: {}", e);
-                e
-            })
+    #[tracing::instrument(
+        name = "UserRepository::create",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "INSERT")
+    )]
+    pub async fn create(&self, new_user: NewUser) -> Result<User, DbError> {
+        with_connection(&self.pool, move |conn| {
+            diesel::insert_into(users::table)
+                .values(&new_user)
+                .returning(User::as_returning())
+                .get_result(conn)
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "UserRepository::list",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn list(&self) -> Result<Vec<User>, DbError> {
+        with_connection(&self.pool, |conn| {
+            users::table
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .load(conn)
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "UserRepository::find_by_username",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn find_by_username(&self, username: &str) -> Result<User, DbError> {
+        let username = username.to_owned();
+        with_connection(&self.pool, move |conn| {
+            users::table
+                .filter(lower(users::username).eq(lower(username)))
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .first(conn)
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "UserRepository::find_by_email",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn find_by_email(&self, email: &str) -> Result<User, DbError> {
+        let email = email.to_owned();
+        with_connection(&self.pool, move |conn| {
+            users::table
+                .filter(lower(users::email).eq(lower(email)))
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .first(conn)
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "UserRepository::find_by_id",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn find_by_id(&self, id: i32) -> Result<User, DbError> {
+        with_connection(&self.pool, move |conn| {
+            users::table
+                .filter(users::id.eq(id))
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .first(conn)
+        })
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "UserRepository::find_by_google_id",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn find_by_google_id(&self, google_id: &str) -> Result<User, DbError> {
+        let google_id = google_id.to_owned();
+        with_connection(&self.pool, move |conn| {
+            users::table
+                .filter(users::google_id.eq(google_id))
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .first(conn)
+        })
+        .await
     }
 
-    pub fn list(&self) -> Result<Vec<User>, diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-        users::table.select(User::as_select()).load(&mut conn)
+    /// Whether a deleted user, not yet purged, is linked to `google_id`.
+    #[tracing::instrument(
+        name = "UserRepository::is_google_id_deleted",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn is_google_id_deleted(&self, google_id: &str) -> Result<bool, DbError> {
+        let google_id = google_id.to_owned();
+        with_connection(&self.pool, move |conn| {
+            diesel::select(diesel::dsl::exists(
+                users::table
+                    .filter(users::google_id.eq(google_id))
+                    .filter(users::deleted_at.is_not_null()),
+            ))
+            .get_result(conn)
+        })
+        .await
     }
 
-    pub fn find_by_username(&self, username: &str) -> Result<User, diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-        users::table
-            .filter(users::username.eq(username))
-            .select(User::as_select())
-            .first(&mut conn)
+    /// Applies `changes` and bumps the version, if the user is at one of
+    /// `versions` (any version for `None`). Returns `None` when no user
+    /// matched.
+    #[tracing::instrument(
+        name = "UserRepository::update",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "UPDATE")
+    )]
+    pub async fn update(
+        &self,
+        id: i32,
+        changes: UserChanges,
+        versions: Option<Vec<i32>>,
+    ) -> Result<Option<User>, DbError> {
+        with_connection(&self.pool, move |conn| {
+            let mut query = diesel::update(users::table)
+                .filter(users::id.eq(id))
+                .filter(users::deleted_at.is_null())
+                .set((
+                    &changes,
+                    users::version.eq(users::version + 1),
+                    users::updated_at.eq(now),
+                ))
+                .into_boxed();
+            if let Some(versions) = versions {
+                query = query.filter(users::version.eq_any(versions));
+            }
+            query
+                .returning(User::as_returning())
+                .get_result(conn)
+                .optional()
+        })
+        .await
     }
 
-    pub fn find_by_email(&self, email: &str) -> Result<User, diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-        users::table
-            .filter(users::email.eq(email))
-            .select(User::as_select())
-            .first(&mut conn)
+    /// Marks the user deleted if it is at one of `versions` (any version
+    /// for `None`). Returns whether a user was deleted.
+    #[tracing::instrument(
+        name = "UserRepository::delete",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "UPDATE")
+    )]
+    pub async fn delete(&self, id: i32, versions: Option<Vec<i32>>) -> Result<bool, DbError> {
+        with_connection(&self.pool, move |conn| {
+            let mut query = diesel::update(users::table)
+                .filter(users::id.eq(id))
+                .filter(users::deleted_at.is_null())
+                .set((
+                    users::deleted_at.eq(now),
+                    users::version.eq(users::version + 1),
+                    users::updated_at.eq(now),
+                ))
+                .into_boxed();
+            if let Some(versions) = versions {
+                query = query.filter(users::version.eq_any(versions));
+            }
+            query.execute(conn).map(|deleted| deleted > 0)
+        })
+        .await
     }
 
-    pub fn find_by_id(&self, id: i32) -> Result<User, diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-        users::table
-            .filter(users::id.eq(id))
-            .select(User::as_select())
-            .first(&mut conn)
+    /// Users deleted within `retention`, most recently deleted first.
+    #[tracing::instrument(
+        name = "UserRepository::list_deleted",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "SELECT")
+    )]
+    pub async fn list_deleted(&self, retention: Duration) -> Result<Vec<User>, DbError> {
+        let retention = interval(retention);
+        with_connection(&self.pool, move |conn| {
+            users::table
+                .filter(users::deleted_at.gt((now - retention).nullable()))
+                .order(users::deleted_at.desc())
+                .select(User::as_select())
+                .load(conn)
+        })
+        .await
     }
 
-    pub fn find_by_google_id(&self, google_id: &str) -> Result<User, diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-        users::table
-            .filter(users::google_id.eq(google_id))
-            .select(User::as_select())
-            .first(&mut conn)
+    /// Undeletes a user deleted within `retention`. Returns `None` when no
+    /// such user exists.
+    #[tracing::instrument(
+        name = "UserRepository::restore",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "UPDATE")
+    )]
+    pub async fn restore(&self, id: i32, retention: Duration) -> Result<Option<User>, DbError> {
+        let retention = interval(retention);
+        with_connection(&self.pool, move |conn| {
+            diesel::update(users::table)
+                .filter(users::id.eq(id))
+                .filter(users::deleted_at.gt((now - retention).nullable()))
+                .set((
+                    users::deleted_at.eq(None::<NaiveDateTime>),
+                    users::version.eq(users::version + 1),
+                    users::updated_at.eq(now),
+                ))
+                .returning(User::as_returning())
+                .get_result(conn)
+                .optional()
+        })
+        .await
     }
 
-    pub fn delete(&self, id: i32) -> Result<(), diesel::result::Error> {
-        let mut conn = self.pool.get().expect("Failed to get db connection");
-        diesel::delete(users::table.filter(users::id.eq(id)))
-            .execute(&mut conn)
-            .map(|_| ())
+    /// Removes users deleted longer than `retention` ago for good.
+    #[tracing::instrument(
+        name = "UserRepository::purge_deleted",
+        skip_all,
+        fields(db.system = "postgresql", db.operation = "DELETE")
+    )]
+    pub async fn purge_deleted(&self, retention: Duration) -> Result<usize, DbError> {
+        let retention = interval(retention);
+        with_connection(&self.pool, move |conn| {
+            diesel::delete(users::table.filter(users::deleted_at.le((now - retention).nullable())))
+                .execute(conn)
+        })
+        .await
     }
 }
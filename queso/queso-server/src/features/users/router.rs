@@ -1,14 +1,53 @@
+use std::sync::Arc;
+
 use axum::{
     Router,
-    routing::{delete, get, post},
+    extract::FromRef,
+    routing::{delete, get, patch, post},
 };
 
 use super::{handler, service::UserService};
+use crate::features::auth::model::{Admins, Keys};
+
+/// State of the users routes; handlers that take `AuthUser` or `AdminUser`
+/// need the JWT keys and the admins.
+#[derive(Clone)]
+pub struct UsersState {
+    pub user_service: UserService,
+    pub keys: Arc<Keys>,
+    pub admins: Admins,
+}
+
+impl FromRef<UsersState> for UserService {
+    fn from_ref(state: &UsersState) -> Self {
+        state.user_service.clone()
+    }
+}
+
+impl FromRef<UsersState> for Arc<Keys> {
+    fn from_ref(state: &UsersState) -> Self {
+        state.keys.clone()
+    }
+}
+
+impl FromRef<UsersState> for Admins {
+    fn from_ref(state: &UsersState) -> Self {
+        state.admins.clone()
+    }
+}
 
-pub fn user_routes() -> Router<UserService> {
+pub fn user_routes() -> Router<UsersState> {
     Router::new()
         .route("/", get(handler::get_users))
         .route("/", post(handler::create_user))
         .route("/{id}", get(handler::get_user))
+        .route("/{id}", patch(handler::update_user))
         .route("/{id}", delete(handler::delete_user))
 }
+
+/// Recovery of deleted users, mounted under `/admin/users`.
+pub fn admin_user_routes() -> Router<UsersState> {
+    Router::new()
+        .route("/deleted", get(handler::get_deleted_users))
+        .route("/{id}/restore", post(handler::restore_user))
+}
@@ -1,68 +1,275 @@
+use std::{sync::Arc, time::Duration};
+
 use super::{
-    model::{NewUser, User, UserError},
+    model::{NewUser, User, UserChanges, UserError},
     repository::UserRepository,
 };
+use crate::{
+    mail::{Email, LogMailer, Mailer},
+    shutdown::Shutdown,
+    telemetry::metrics,
+};
 
 #[derive(Clone)]
 pub struct UserService {
     repository: UserRepository,
+    mailer: Arc<dyn Mailer>,
+    shutdown: Shutdown,
+    enumeration_protection: bool,
+    deleted_retention: Duration,
 }
 
+/// How long deleted users can be restored unless configured otherwise.
+const DEFAULT_DELETED_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often users deleted longer than the retention period are purged.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 impl UserService {
     pub fn new(repository: UserRepository) -> Self {
-        Self { repository }
+        Self {
+            repository,
+            mailer: Arc::new(LogMailer),
+            shutdown: Shutdown::new(),
+            enumeration_protection: false,
+            deleted_retention: DEFAULT_DELETED_RETENTION,
+        }
+    }
+
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    /// Tracks outgoing mail so shutdown waits for it to be sent.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// When enabled, sign-up responses no longer reveal whether a username or
+    /// email is already registered; conflicts are reported to the address owner
+    /// by email instead.
+    pub fn with_enumeration_protection(mut self, enabled: bool) -> Self {
+        self.enumeration_protection = enabled;
+        self
+    }
+
+    /// How long deleted users can be restored before they are purged.
+    pub fn with_deleted_retention(mut self, retention: Duration) -> Self {
+        self.deleted_retention = retention;
+        self
+    }
+
+    pub fn enumeration_protection(&self) -> bool {
+        self.enumeration_protection
     }
 
+    /// Inserts the user in a single statement; the database's unique indexes
+    /// decide conflicts, which map to `UsernameExists` / `EmailExists`.
     pub async fn create_user(&self, new_user: NewUser) -> Result<User, UserError> {
-        // Check if username exists
-        if self.repository.find_by_username(&new_user.username).is_ok() {
-            return Err(UserError::UsernameExists);
-        }
+        let user = self
+            .repository
+            .create(new_user)
+            .await
+            .map_err(UserError::from)?;
 
-        // Check if email exists
-        if self.repository.find_by_email(&new_user.email).is_ok() {
-            return Err(UserError::EmailExists);
-        }
+        let provider = if user.google_id.is_some() {
+            "google"
+        } else {
+            "password"
+        };
+        metrics::record_signup(provider);
+        Ok(user)
+    }
 
-        // Create user
-        self.repository
-            .create(&new_user)
-            .map_err(UserError::DatabaseError)
+    /// Registers a user without telling the caller whether the account was
+    /// created. Both outcomes send an email to the submitted address, so only
+    /// its owner learns whether it was already in use.
+    pub async fn register_quietly(&self, new_user: NewUser) -> Result<(), UserError> {
+        let email = new_user.email.clone();
+        let username = new_user.username.clone();
+
+        let message = match self.create_user(new_user).await {
+            Ok(user) => welcome_email(&user),
+            Err(UserError::EmailExists) => account_exists_email(&email),
+            Err(UserError::UsernameExists) => username_taken_email(&email, &username),
+            Err(e) => return Err(e),
+        };
+
+        // Deliver off the request path so response timing doesn't depend on
+        // which email was sent
+        let mailer = self.mailer.clone();
+        self.shutdown.spawn(async move {
+            if let Err(e) = mailer.send(message).await {
+                tracing::error!("Failed to send sign-up email: {}", e);
+            }
+        });
+
+        Ok(())
     }
 
     pub async fn get_user(&self, id: i32) -> Result<User, UserError> {
         self.repository
             .find_by_id(id)
-            .map_err(UserError::DatabaseError)
+            .await
+            .map_err(UserError::from)
     }
 
     pub async fn get_users(&self) -> Result<Vec<User>, UserError> {
-        self.repository.list().map_err(UserError::DatabaseError)
+        self.repository.list().await.map_err(UserError::from)
     }
 
-    pub async fn delete_user(&self, id: i32) -> Result<(), UserError> {
+    /// Updates the user if it is still at one of `versions`, as listed in
+    /// `If-Match`; `None` updates any version.
+    pub async fn update_user(
+        &self,
+        id: i32,
+        changes: UserChanges,
+        versions: Option<Vec<i32>>,
+    ) -> Result<User, UserError> {
+        let updated = self
+            .repository
+            .update(id, changes, versions)
+            .await
+            .map_err(UserError::from)?;
+
+        match updated {
+            Some(user) => Ok(user),
+            None => Err(self.precondition_failure(id).await),
+        }
+    }
+
+    /// Deletes the user if it is still at one of `versions`; `None` deletes
+    /// any version. The user can be restored until the retention period
+    /// ends.
+    pub async fn delete_user(&self, id: i32, versions: Option<Vec<i32>>) -> Result<(), UserError> {
+        let deleted = self
+            .repository
+            .delete(id, versions)
+            .await
+            .map_err(UserError::from)?;
+
+        if deleted {
+            Ok(())
+        } else {
+            Err(self.precondition_failure(id).await)
+        }
+    }
+
+    /// Users deleted within the retention period, most recent first.
+    pub async fn get_deleted_users(&self) -> Result<Vec<User>, UserError> {
         self.repository
-            .find_by_id(id)
-            .map_err(UserError::DatabaseError)?;
+            .list_deleted(self.deleted_retention)
+            .await
+            .map_err(UserError::from)
+    }
+
+    /// Undoes the deletion of a user; `NotFound` once the retention period
+    /// has passed.
+    pub async fn restore_user(&self, id: i32) -> Result<User, UserError> {
+        self.repository
+            .restore(id, self.deleted_retention)
+            .await
+            .map_err(UserError::from)?
+            .ok_or(UserError::NotFound)
+    }
 
-        self.repository.delete(id).map_err(UserError::DatabaseError)
+    /// Removes users deleted longer than the retention period ago.
+    pub async fn purge_deleted_users(&self) -> Result<usize, UserError> {
+        self.repository
+            .purge_deleted(self.deleted_retention)
+            .await
+            .map_err(UserError::from)
+    }
+
+    /// Why a conditional change to `id` matched nothing: the user is gone,
+    /// or it is at another version.
+    async fn precondition_failure(&self, id: i32) -> UserError {
+        match self.repository.find_by_id(id).await {
+            Ok(_) => UserError::VersionMismatch,
+            Err(e) => UserError::from(e),
+        }
     }
 
     pub async fn find_by_username(&self, username: &str) -> Result<User, UserError> {
         self.repository
             .find_by_username(username)
-            .map_err(UserError::DatabaseError)
+            .await
+            .map_err(UserError::from)
     }
 
     pub async fn find_by_email(&self, email: &str) -> Result<User, UserError> {
         self.repository
             .find_by_email(email)
-            .map_err(UserError::DatabaseError)
+            .await
+            .map_err(UserError::from)
     }
 
     pub async fn find_by_google_id(&self, google_id: &str) -> Result<User, UserError> {
         self.repository
             .find_by_google_id(google_id)
-            .map_err(UserError::DatabaseError)
+            .await
+            .map_err(UserError::from)
+    }
+
+    /// Whether the Google account belongs to a deleted user that has not
+    /// been purged yet.
+    pub async fn is_deleted_google_account(&self, google_id: &str) -> Result<bool, UserError> {
+        self.repository
+            .is_google_id_deleted(google_id)
+            .await
+            .map_err(UserError::from)
+    }
+}
+
+/// Purges users past the retention period in the background until shutdown.
+pub fn spawn_purge_worker(service: UserService, shutdown: &Shutdown) {
+    let worker = shutdown.clone();
+    shutdown.spawn(async move {
+        loop {
+            tokio::select! {
+                _ = worker.cancelled() => break,
+                _ = tokio::time::sleep(PURGE_INTERVAL) => {}
+            }
+
+            match service.purge_deleted_users().await {
+                Ok(purged) => tracing::debug!(purged, "Purged deleted users"),
+                Err(e) => tracing::warn!(error = %e, "Failed to purge deleted users"),
+            }
+        }
+    });
+}
+
+fn welcome_email(user: &User) -> Email {
+    Email {
+        to: user.email.clone(),
+        subject: "Welcome to Queso".to_string(),
+        body: format!(
+            "Hi {},\n\nYour Queso account has been created. You can now sign in with this email address.",
+            user.username
+        ),
+    }
+}
+
+fn account_exists_email(email: &str) -> Email {
+    Email {
+        to: email.to_string(),
+        subject: "Sign-up attempt for your Queso account".to_string(),
+        body: "Someone tried to create a Queso account with this email address, but an account \
+               already exists. If this was you, sign in instead. If not, you can ignore this email."
+            .to_string(),
+    }
+}
+
+fn username_taken_email(email: &str, username: &str) -> Email {
+    Email {
+        to: email.to_string(),
+        subject: "Your Queso sign-up could not be completed".to_string(),
+        body: format!(
+            "The username \"{}\" is already taken, so your account was not created. \
+             Please sign up again with a different username.",
+            username
+        ),
     }
 }
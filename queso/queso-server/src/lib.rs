@@ -1,6 +1,14 @@
+pub mod api;
 pub mod config;
 pub mod features;
+pub mod mail;
 pub mod openapi;
 pub mod schema;
 pub mod server;
+pub mod shutdown;
+pub mod static_files;
+pub mod telemetry;
 pub use server::run_server;
+
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
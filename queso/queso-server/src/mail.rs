@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("Failed to deliver email: {0}")]
+    Delivery(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// Mail transport, abstracted so delivery can be swapped out per deployment
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+// Writes outgoing mail to the log instead of delivering it
+#[derive(Debug, Default, Clone)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        tracing::info!(subject = %email.subject, "Outgoing email (not delivered)");
+        tracing::debug!(to = %email.to, body = %email.body, "Outgoing email body");
+        Ok(())
+    }
+}
@@ -1,32 +1,32 @@
-use dotenvy::dotenv;
-use git2::Repository;
-use std::{env, path::PathBuf};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-fn find_git_root() -> PathBuf {
-    let current_dir = env::current_dir().expect("Failed to get current directory");
-    let repo = Repository::discover(current_dir).expect("Failed to find git repository");
-    repo.path()
-        .parent()
-        .expect("Failed to get repository root")
-        .to_path_buf()
-}
+use queso_server::{
+    config::{Settings, resolve_root},
+    telemetry,
+};
 
 #[tokio::main]
 async fn main() {
-    // Load .env from workspace root
-    let workspace_root = find_git_root();
-    env::set_current_dir(&workspace_root).expect("Failed to change to workspace root");
-    dotenv().ok();
+    // Load .env from the deployment root (falls back to the git checkout in
+    // debug builds)
+    let root = resolve_root();
+    dotenvy::from_path(root.join(".env")).ok();
+
+    // Load and validate configuration before touching anything else
+    let settings = match Settings::load(&root) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Initialize tracing; the guard flushes exported spans on exit
+    let _telemetry = telemetry::init(&settings.telemetry, &settings.logging);
+    tracing::info!(
+        "Loaded configuration for the {} profile from {}",
+        settings.profile,
+        root.display()
+    );
 
     // Start the server
-    queso_server::run_server().await;
+    queso_server::run_server(settings).await;
 }
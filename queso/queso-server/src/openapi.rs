@@ -1,27 +1,27 @@
-use utoipa::OpenApi;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
 
+use crate::{api::version::V1_PREFIX, features::auth::session::SESSION_COOKIE};
+
+/// Unversioned endpoints and the metadata shared by every version's
+/// document.
 #[derive(OpenApi)]
 #[openapi(
     paths(
-        crate::features::users::handler::get_user,
-        crate::features::users::handler::get_users,
-        crate::features::users::handler::create_user,
-        crate::features::users::handler::delete_user,
-        crate::features::auth::oauth::google_login,
-        crate::features::auth::oauth::google_callback,
+        crate::features::health::handler::live,
+        crate::features::health::handler::ready,
     ),
     components(
         schemas(
-            crate::features::users::model::User,
-            crate::features::users::model::NewUser,
-            crate::features::users::model::GoogleUser,
-            crate::features::auth::model::LoginResponse,
-            crate::features::auth::model::OAuthCallback,
+            crate::features::health::model::HealthResponse,
+            crate::features::health::model::HealthStatus,
+            crate::features::health::model::CheckResult,
         )
     ),
     tags(
-        (name = "users", description = "User management endpoints"),
-        (name = "auth", description = "Authentication endpoints")
+        (name = "health", description = "Liveness and readiness probes")
     ),
     info(
         title = "Queso API",
@@ -39,3 +39,246 @@ use utoipa::OpenApi;
     )
 )]
 pub struct ApiDoc;
+
+/// The v1 API. Paths are relative to [`V1_PREFIX`]; use [`v1`] for the full
+/// document.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::features::users::handler::get_user,
+        crate::features::users::handler::get_users,
+        crate::features::users::handler::create_user,
+        crate::features::users::handler::update_user,
+        crate::features::users::handler::delete_user,
+        crate::features::users::handler::get_deleted_users,
+        crate::features::users::handler::restore_user,
+        crate::features::auth::handler::login,
+        crate::features::auth::handler::me,
+        crate::features::auth::handler::logout,
+        crate::features::auth::oauth::google_login,
+        crate::features::auth::oauth::google_callback,
+    ),
+    components(
+        schemas(
+            crate::features::users::model::User,
+            crate::features::users::model::NewUser,
+            crate::features::users::model::GoogleUser,
+            crate::features::users::handler::CreateUserRequest,
+            crate::features::users::handler::UpdateUserRequest,
+            crate::features::users::handler::SignupAcceptedResponse,
+            crate::features::auth::model::CurrentUser,
+            crate::features::auth::model::EmailLoginRequest,
+            crate::features::auth::model::LoginResponse,
+            crate::features::auth::model::OAuthCallback,
+            crate::features::auth::oauth::OAuthUrlResponse,
+            crate::api::ProblemDetails,
+            crate::api::FieldError,
+        )
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "users", description = "User management endpoints"),
+        (name = "admin", description = "Administration endpoints"),
+        (name = "auth", description = "Authentication endpoints")
+    )
+)]
+pub struct V1Doc;
+
+/// Defines the schemes named in the handlers' `security(...)` attributes.
+/// Either one authenticates a request.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "jwt",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some("Token returned by the login endpoints"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                SESSION_COOKIE,
+                "HttpOnly session cookie set at login when cookie sessions are enabled; \
+                 unsafe methods also need the X-CSRF-Token header",
+            ))),
+        );
+    }
+}
+
+/// The OpenAPI document for `/api/v1`. The deprecated unversioned aliases
+/// are left out.
+pub fn v1() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi().nest(V1_PREFIX, V1Doc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::settings::GoogleSettings,
+        features::{
+            auth::{
+                AuthService,
+                model::{Admins, Keys},
+                oauth::{OAuthConfig, OAuthState},
+            },
+            health::{HealthService, health_routes},
+            users::{UserRepository, UserService},
+        },
+        server::v1_routes,
+        shutdown::Shutdown,
+        test_support,
+    };
+    use axum::Router;
+    use std::collections::BTreeSet;
+
+    /// Routes served but deliberately left out of the document.
+    const UNDOCUMENTED: &[(&str, &str)] = &[
+        // Alias of /health/ready for older probes
+        ("GET", "/health"),
+    ];
+
+    /// `(method, path)` for every route in `router`, nested under `prefix`.
+    /// axum has no public route listing, so this reads the router's `Debug`
+    /// output, which pairs each route id with its path and `Allow` header.
+    fn routes<S>(prefix: &str, router: &Router<S>) -> BTreeSet<(String, String)> {
+        let debug = format!("{:?}", router);
+        let path_router = debug.split("fallback_router:").next().unwrap();
+        let (endpoints, node) = path_router.split_once("node: Node").unwrap();
+
+        let field = |chunk: &str, start: &str, end: &str| -> Option<String> {
+            let rest = &chunk[chunk.find(start)? + start.len()..];
+            Some(rest[..rest.find(end)?].to_string())
+        };
+        let paths: Vec<(String, String)> = node
+            .split("RouteId(")
+            .skip(1)
+            .filter_map(|chunk| Some((field(chunk, "", ")")?, field(chunk, "\"", "\"")?)))
+            .collect();
+
+        let mut routes = BTreeSet::new();
+        for chunk in endpoints.split("RouteId(").skip(1) {
+            let id = field(chunk, "", ")").unwrap();
+            let allow = field(chunk, "allow_header: Bytes(b\"", "\"")
+                .unwrap_or_else(|| panic!("route {} is not a method router", id));
+            let (_, path) = paths.iter().find(|(route, _)| *route == id).unwrap();
+            for method in allow.split(',').filter(|method| *method != "HEAD") {
+                routes.insert((method.to_string(), format!("{}{}", prefix, path)));
+            }
+        }
+        routes
+    }
+
+    /// Every `(method, path)` operation in the document.
+    fn operations(doc: &utoipa::openapi::OpenApi) -> BTreeSet<(String, String)> {
+        let mut operations = BTreeSet::new();
+        for (path, item) in &doc.paths.paths {
+            let methods = [
+                ("GET", &item.get),
+                ("POST", &item.post),
+                ("PUT", &item.put),
+                ("PATCH", &item.patch),
+                ("DELETE", &item.delete),
+            ];
+            for (method, operation) in methods {
+                if operation.is_some() {
+                    operations.insert((method.to_string(), path.clone()));
+                }
+            }
+        }
+        operations
+    }
+
+    fn v1_router() -> Router {
+        let user_service = UserService::new(UserRepository::new(test_support::offline_pool()));
+        let google = GoogleSettings {
+            client_id: "client-id".to_string(),
+            client_secret: "client-secret".to_string(),
+            auth_url: "https://accounts.example.com/auth".to_string(),
+            token_url: "https://accounts.example.com/token".to_string(),
+            redirect_url: "http://localhost:5173/auth/google/callback".to_string(),
+            userinfo_url: "https://accounts.example.com/userinfo".to_string(),
+        };
+
+        v1_routes(
+            user_service.clone(),
+            Admins::default(),
+            OAuthState {
+                oauth_config: OAuthConfig::new(&google).unwrap(),
+                auth_service: AuthService::new(user_service.clone(), Keys::new(b"openapi-test")),
+                user_service,
+            },
+        )
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let mut served = routes(V1_PREFIX, &v1_router());
+        let health: Router = Router::new()
+            .nest("/health", health_routes())
+            .with_state(HealthService::new(Shutdown::new()));
+        served.extend(routes("", &health));
+        for (method, path) in UNDOCUMENTED {
+            served.remove(&(method.to_string(), path.to_string()));
+        }
+        let documented = operations(&v1());
+
+        let undocumented: Vec<_> = served.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "routes missing from the OpenAPI document: {:?}",
+            undocumented
+        );
+        let unrouted: Vec<_> = documented.difference(&served).collect();
+        assert!(
+            unrouted.is_empty(),
+            "documented operations with no route: {:?}",
+            unrouted
+        );
+    }
+
+    #[test]
+    fn test_security_schemes_are_defined() {
+        let doc = v1();
+        let schemes = &doc.components.as_ref().unwrap().security_schemes;
+
+        for operation in [
+            &doc.paths.paths["/api/v1/auth/me"].get,
+            &doc.paths.paths["/api/v1/auth/logout"].post,
+            &doc.paths.paths["/api/v1/users/{id}"].patch,
+            &doc.paths.paths["/api/v1/admin/users/deleted"].get,
+            &doc.paths.paths["/api/v1/admin/users/{id}/restore"].post,
+        ] {
+            let security = operation.as_ref().unwrap().security.as_ref().unwrap();
+            let json = serde_json::to_value(security).unwrap();
+            for requirement in json.as_array().unwrap() {
+                for name in requirement.as_object().unwrap().keys() {
+                    assert!(schemes.contains_key(name), "{} is not defined", name);
+                }
+            }
+        }
+        assert!(matches!(schemes["jwt"], SecurityScheme::Http(_)));
+    }
+
+    #[test]
+    fn test_v1_document_uses_versioned_paths() {
+        let doc = v1();
+        let paths: Vec<&str> = doc.paths.paths.keys().map(String::as_str).collect();
+
+        assert!(paths.contains(&"/api/v1/users/{id}"));
+        assert!(paths.contains(&"/api/v1/auth/google/callback"));
+        assert!(paths.contains(&"/health/live"));
+        assert!(!paths.iter().any(|path| path.starts_with("/api/users")));
+
+        let schemas = &doc.components.as_ref().unwrap().schemas;
+        assert!(schemas.contains_key("User"));
+        assert!(schemas.contains_key("HealthResponse"));
+    }
+}
@@ -9,5 +9,30 @@ diesel::table! {
         created_at -> Timestamp,
         google_id -> Nullable<Varchar>,
         avatar_url -> Nullable<Varchar>,
+        version -> Int4,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
+
+diesel::table! {
+    rate_limit_buckets (key) {
+        key -> Varchar,
+        tokens -> Float8,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (key) {
+        key -> Varchar,
+        fingerprint -> Varchar,
+        locked_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        response_status -> Nullable<Int2>,
+        response_headers -> Nullable<Jsonb>,
+        response_body -> Nullable<Bytea>,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(idempotency_keys, rate_limit_buckets, users,);
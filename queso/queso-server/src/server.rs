@@ -1,87 +1,273 @@
+use std::{sync::Arc, time::Duration};
+
 use crate::{
-    config::database::{establish_connection_pool, run_migrations},
+    api::{
+        cors,
+        idempotency::{self, Idempotency},
+        rate_limit::{self, MemoryStore, Policy, PostgresStore, RateLimiter},
+        request_id::request_id,
+        security_headers::security_headers,
+        version::{LEGACY_PREFIX, V1_PREFIX, legacy_alias},
+    },
+    config::{
+        Settings,
+        database::{DbPool, establish_connection_pool, run_migrations},
+        settings::{IdempotencySettings, RateLimitBackend, RateLimitSettings},
+    },
     features::{
         auth::{
+            model::{Admins, Keys},
             oauth::{OAuthConfig, OAuthState},
             router::auth_routes,
             service::AuthService,
+            session,
+        },
+        health::{
+            HealthService,
+            checks::{DatabaseCheck, MigrationsCheck},
+            health_routes,
+        },
+        users::{
+            self,
+            repository::UserRepository,
+            router::{UsersState, admin_user_routes, user_routes},
+            service::UserService,
         },
-        users::{repository::UserRepository, router::user_routes, service::UserService},
     },
-    openapi::ApiDoc,
-};
-use axum::{Router, routing::get};
-use tower_http::{
-    cors::{Any, CorsLayer},
-    services::{ServeDir, ServeFile},
+    openapi,
+    shutdown::{self, Shutdown},
+    static_files::static_files,
+    telemetry::{
+        metrics::{self, MetricsState},
+        trace,
+    },
 };
-use utoipa::OpenApi;
+use axum::{Router, http::Method, middleware, response::Redirect, routing::get};
 use utoipa_swagger_ui::SwaggerUi;
 
-pub async fn run_server() {
-    // Create database pool
-    let pool = establish_connection_pool();
+/// Per-client limits; the credential and sign-up endpoints are kept tight to
+/// slow down guessing and account spam.
+fn rate_limiter(
+    settings: &RateLimitSettings,
+    pool: &DbPool,
+    keys: Arc<Keys>,
+    shutdown: &Shutdown,
+) -> RateLimiter {
+    let limiter = match settings.store {
+        RateLimitBackend::Memory => RateLimiter::new(MemoryStore::new()),
+        RateLimitBackend::Postgres => {
+            let store = PostgresStore::new(pool.clone());
+            rate_limit::spawn_purge_worker(store.clone(), shutdown);
+            RateLimiter::new(store)
+        }
+    };
 
-    // Run migrations
-    tracing::info!("Running database migrations...");
-    run_migrations(&pool);
+    let minute = Duration::from_secs(60);
+    let policies = [
+        (
+            Method::POST,
+            "/users",
+            Policy::per_ip("signup", 5, Duration::from_secs(60 * 60)),
+        ),
+        (
+            Method::POST,
+            "/auth/login/email",
+            Policy::per_ip("login", 10, minute),
+        ),
+        (
+            Method::GET,
+            "/auth/google/login",
+            Policy::per_ip("oauth", 20, minute),
+        ),
+        (
+            Method::POST,
+            "/auth/google/callback",
+            Policy::per_ip("oauth", 20, minute),
+        ),
+    ];
+
+    let mut limiter = limiter
+        .with_keys(keys)
+        .with_trusted_proxy_hops(settings.trusted_proxy_hops)
+        .with_default(Policy::per_user("api", 300, minute));
+    // A versioned path and its legacy alias share the policy and its buckets
+    for (method, path, policy) in policies {
+        for prefix in [V1_PREFIX, LEGACY_PREFIX] {
+            limiter = limiter.with_route(method.clone(), format!("{}{}", prefix, path), policy);
+        }
+    }
+    limiter
+}
+
+/// Idempotency keys are kept in the database so a retry reaching another
+/// instance is still recognized.
+fn idempotency_keys(
+    settings: &IdempotencySettings,
+    trusted_proxy_hops: usize,
+    pool: &DbPool,
+    keys: Arc<Keys>,
+    shutdown: &Shutdown,
+) -> Idempotency {
+    let store = idempotency::PostgresStore::new(pool.clone());
+    idempotency::spawn_purge_worker(store.clone(), shutdown);
+    Idempotency::new(store, settings.ttl)
+        .with_keys(keys)
+        .with_trusted_proxy_hops(trusted_proxy_hops)
+}
+
+/// Everything served under `/api/v1`.
+pub(crate) fn v1_routes(
+    user_service: UserService,
+    admins: Admins,
+    oauth_state: OAuthState,
+) -> Router {
+    let users = UsersState {
+        user_service,
+        keys: oauth_state.auth_service.keys(),
+        admins,
+    };
+    Router::new()
+        .nest("/users", user_routes().with_state(users.clone()))
+        .nest("/admin/users", admin_user_routes().with_state(users))
+        .nest("/auth", auth_routes().with_state(oauth_state))
+}
+
+/// The whole application, API, docs, metrics and UI, behind every
+/// middleware layer. Background workers are tied to `shutdown`.
+pub fn app(settings: &Settings, pool: DbPool, shutdown: &Shutdown) -> Router {
+    // Install the metrics recorder before anything records
+    let metrics_handle = metrics::recorder();
 
     // Create repositories
     let user_repository = UserRepository::new(pool.clone());
 
+    // Hardened mode hides whether accounts exist from login and sign-up responses
+    let hardened_mode = settings.auth.hardened_mode;
+
     // Create services
-    let user_service = UserService::new(user_repository);
-    let auth_service = AuthService::new(user_service.clone());
+    let user_service = UserService::new(user_repository)
+        .with_shutdown(shutdown.clone())
+        .with_enumeration_protection(hardened_mode)
+        .with_deleted_retention(settings.users.deleted_retention);
+    users::service::spawn_purge_worker(user_service.clone(), shutdown);
+    let auth_service = AuthService::new(
+        user_service.clone(),
+        Keys::new(settings.auth.jwt_secret.as_bytes()),
+    )
+    .with_sessions(settings.auth.session.clone())
+    .with_enumeration_protection(hardened_mode);
 
     // Create OAuth config
-    let oauth_config = OAuthConfig::new(
-        std::env::var("GOOGLE_CLIENT_ID").expect("GOOGLE_CLIENT_ID must be set"),
-        std::env::var("GOOGLE_CLIENT_SECRET").expect("GOOGLE_CLIENT_SECRET must be set"),
-        std::env::var("GOOGLE_AUTH_URL").expect("GOOGLE_AUTH_URL must be set"),
-        std::env::var("GOOGLE_TOKEN_URL").expect("GOOGLE_TOKEN_URL must be set"),
-        std::env::var("GOOGLE_REDIRECT_URL").expect("GOOGLE_REDIRECT_URL must be set"),
-    )
-    .expect("Failed to create OAuth config");
+    let oauth_config = OAuthConfig::new(&settings.google).expect("Failed to create OAuth config");
+
+    // Readiness covers the database and schema; add checks for new dependencies here
+    let health_service = HealthService::new(shutdown.clone())
+        .with_check(DatabaseCheck::new(pool.clone()))
+        .with_check(MigrationsCheck::new(pool.clone()));
 
-    // Create CORS layer
-    let cors = CorsLayer::new()
-        .allow_methods(Any)
-        .allow_headers(Any)
-        .allow_origin(Any);
+    let rate_limiter = rate_limiter(&settings.rate_limit, &pool, auth_service.keys(), shutdown);
 
-    // Setup static file serving with SPA fallback
-    let static_files_service =
-        ServeDir::new("dist").not_found_service(ServeFile::new("dist/index.html"));
+    // Only the configured origins may call the API from a browser
+    let cors = cors::cors_layer(&settings.cors);
+
+    let v1 = v1_routes(
+        user_service.clone(),
+        Admins::new(settings.auth.admin_user_ids.iter().copied()),
+        OAuthState {
+            oauth_config: oauth_config.clone(),
+            auth_service: auth_service.clone(),
+            user_service,
+        },
+    );
 
     // Build our application with routes
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .nest("/api/users", user_routes().with_state(user_service.clone()))
-        .nest(
-            "/api/auth",
-            auth_routes().with_state(OAuthState {
-                oauth_config: oauth_config.clone(),
-                auth_service: auth_service.clone(),
-                user_service,
-            }),
+    let mut app = Router::new()
+        .route("/metrics", get(metrics::render))
+        .with_state(MetricsState {
+            handle: metrics_handle,
+            pool: pool.clone(),
+        })
+        .nest("/health", health_routes().with_state(health_service))
+        .nest(V1_PREFIX, v1.clone())
+        // Unversioned aliases for clients predating /api/v1
+        .nest(LEGACY_PREFIX, v1.layer(middleware::from_fn(legacy_alias)))
+        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/v1/openapi.json", openapi::v1()))
+        .route(
+            "/api-docs/openapi.json",
+            get(|| async { Redirect::permanent("/api-docs/v1/openapi.json") }),
         )
-        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .fallback_service(static_files_service)
-        .layer(cors);
+        .fallback_service(static_files(&settings.server.static_dir));
+
+    // Inside the rate limiter, so replayed retries still count against it
+    if settings.idempotency.enabled {
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(idempotency_keys(
+                &settings.idempotency,
+                settings.rate_limit.trusted_proxy_hops,
+                &pool,
+                auth_service.keys(),
+                shutdown,
+            )),
+            idempotency::idempotency,
+        ));
+    }
+
+    // Outside idempotency, so a rejected request never takes up its key
+    app = app.layer(middleware::from_fn(session::csrf));
+
+    if settings.rate_limit.enabled {
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(rate_limiter),
+            rate_limit::rate_limit,
+        ));
+    }
+
+    app.layer(middleware::from_fn(metrics::track_http))
+        .layer(trace::http_layer())
+        .layer(middleware::from_fn(request_id))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(settings.security.clone()),
+            security_headers,
+        ))
+        .layer(cors)
+}
+
+pub async fn run_server(settings: Settings) {
+    // Create database pool
+    let pool = establish_connection_pool(&settings.database.url, &settings.database.pool);
+
+    // Run migrations
+    tracing::info!("Running database migrations...");
+    run_migrations(&pool);
 
-    // Get server host and port from environment
-    let host = std::env::var("SERVER_HOST").expect("SERVER_HOST must be set");
-    let port = std::env::var("API_PORT").expect("API_PORT must be set");
-    let addr = format!("{}:{}", host, port);
+    // Flipped by SIGTERM/SIGINT; readiness and background work follow it
+    let shutdown = Shutdown::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown::signal().await;
+            tracing::info!("Shutdown signal received");
+            shutdown.begin();
+        }
+    });
+
+    let app = app(&settings, pool, &shutdown);
+
+    let addr = settings.server.addr();
 
     // Run it
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
     tracing::info!("Server listening on http://{}", addr);
     tracing::info!("API documentation available at http://{}/swagger-ui", addr);
 
-    axum::serve(listener, app).await.unwrap();
-}
-
-async fn health_check() -> axum::http::StatusCode {
-    axum::http::StatusCode::OK
+    shutdown::serve(
+        listener,
+        app,
+        shutdown,
+        settings.server.pre_stop_delay,
+        settings.server.shutdown_timeout,
+    )
+    .await
+    .unwrap();
+    tracing::info!("Server stopped");
 }
@@ -0,0 +1,262 @@
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Coordinates shutdown between the HTTP server, readiness reporting and
+/// background work.
+///
+/// Background tasks are spawned through [`Shutdown::spawn`] so they can be
+/// awaited before the process exits. Long-running workers should select on
+/// [`Shutdown::cancelled`] between jobs, never in the middle of one.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts shutting down; idempotent.
+    pub fn begin(&self) {
+        self.token.cancel();
+        self.tasks.close();
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Resolves once shutdown has begun.
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Spawns a background task that shutdown waits for.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    /// Waits up to `timeout` for background tasks, returning whether they
+    /// all finished.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.tasks.close();
+        tokio::time::timeout(timeout, self.tasks.wait())
+            .await
+            .is_ok()
+    }
+}
+
+/// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Serves `app` until `shutdown` begins. Readiness fails from then on, but
+/// connections are still accepted for `pre_stop_delay` so load balancers
+/// see it and stop routing here. Then the listener closes and in-flight
+/// requests and background tasks get `drain_timeout` to finish before
+/// returning. Handlers can read the peer address through
+/// `ConnectInfo<SocketAddr>`.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    shutdown: Shutdown,
+    pre_stop_delay: Duration,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown.cancelled().await;
+            if !pre_stop_delay.is_zero() {
+                tracing::info!(
+                    "Shutting down, still accepting connections for {:?}",
+                    pre_stop_delay
+                );
+                tokio::time::sleep(pre_stop_delay).await;
+            }
+        }
+    });
+
+    let drained = async {
+        server.await?;
+        shutdown.tasks.close();
+        shutdown.tasks.wait().await;
+        Ok(())
+    };
+
+    let deadline = async {
+        shutdown.cancelled().await;
+        tokio::time::sleep(pre_stop_delay).await;
+        tracing::info!(
+            "Shutting down, draining connections and background tasks for up to {:?}",
+            drain_timeout
+        );
+        tokio::time::sleep(drain_timeout).await;
+    };
+
+    tokio::select! {
+        result = drained => result,
+        _ = deadline => {
+            tracing::warn!("Drain timeout elapsed, abandoning remaining connections and tasks");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::health::{HealthService, health_routes};
+    use axum::routing::get;
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_background_tasks() {
+        let shutdown = Shutdown::new();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        shutdown.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            tx.send(()).unwrap();
+        });
+
+        shutdown.begin();
+
+        assert!(shutdown.is_shutting_down());
+        assert!(shutdown.drain(Duration::from_secs(5)).await);
+        rx.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_gives_up_after_timeout() {
+        let shutdown = Shutdown::new();
+        shutdown.spawn(std::future::pending());
+        shutdown.begin();
+
+        assert!(!shutdown.drain(Duration::from_millis(20)).await);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_finish_during_shutdown() {
+        let started = Arc::new(Notify::new());
+        let app = Router::new().route(
+            "/slow",
+            get({
+                let started = started.clone();
+                || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    "done"
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            shutdown.clone(),
+            Duration::ZERO,
+            Duration::from_secs(5),
+        ));
+
+        let request = tokio::spawn(reqwest::get(format!("http://{}/slow", addr)));
+        started.notified().await;
+        shutdown.begin();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+
+        // No new connections are accepted once drained
+        assert!(reqwest::get(format!("http://{}/slow", addr)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_serve_gives_up_after_drain_timeout() {
+        let app = Router::new().route("/hang", get(std::future::pending::<()>));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            shutdown.clone(),
+            Duration::ZERO,
+            Duration::from_millis(100),
+        ));
+
+        let _request = tokio::spawn(reqwest::get(format!("http://{}/hang", addr)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.begin();
+
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server did not stop after the drain timeout")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_readiness_fails_before_the_listener_closes() {
+        let shutdown = Shutdown::new();
+        let app = Router::new()
+            .nest("/health", health_routes())
+            .with_state(HealthService::new(shutdown.clone()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ready = format!("http://{}/health/ready", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            shutdown.clone(),
+            Duration::from_millis(300),
+            Duration::from_secs(5),
+        ));
+
+        let response = reqwest::get(&ready).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        shutdown.begin();
+
+        // Load balancers polling during the pre-stop delay see the failure
+        let response = reqwest::get(&ready).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        server.await.unwrap().unwrap();
+        assert!(reqwest::get(&ready).await.is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use std::path::{Path, PathBuf};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use tower::ServiceExt;
+use tower_http::services::ServeDir;
+
+use crate::api::security_headers::CspNonce;
+
+/// Written into `index.html` by Vite's `html.cspNonce` option and replaced
+/// with the request's nonce when the page is served.
+const NONCE_PLACEHOLDER: &str = "__CSP_NONCE__";
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const NO_CACHE: &str = "no-cache";
+/// Unhashed files like `favicon.ico` may change between deploys.
+const SHORT_LIVED: &str = "public, max-age=3600";
+
+#[derive(Clone)]
+struct StaticFiles {
+    dir: PathBuf,
+    files: ServeDir,
+}
+
+/// Serves the built UI from `dir` as a router fallback.
+///
+/// - hashed build assets are cached forever, `index.html` is revalidated on
+///   every load
+/// - precompressed `.br` and `.gz` siblings are served to clients accepting
+///   them
+/// - responses carry an `ETag` and honour `If-None-Match`
+/// - unknown paths requested as documents get `index.html` so client-side
+///   routes load; other unknown paths are a plain `404`
+pub fn static_files(dir: &Path) -> Router {
+    let files = ServeDir::new(dir)
+        .precompressed_br()
+        .precompressed_gzip()
+        .append_index_html_on_directories(false);
+
+    Router::new().fallback(serve).with_state(StaticFiles {
+        dir: dir.to_path_buf(),
+        files,
+    })
+}
+
+async fn serve(State(state): State<StaticFiles>, req: Request) -> Response {
+    let path = req.uri().path().to_string();
+    if path == "/" || path == "/index.html" {
+        return index(&state.dir, req.extensions().get::<CspNonce>()).await;
+    }
+
+    let wants_document = accepts_html(req.headers());
+    let nonce = req.extensions().get::<CspNonce>().cloned();
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+
+    let Ok(mut response) = state.files.clone().oneshot(req).await;
+    match response.status() {
+        StatusCode::NOT_FOUND if wants_document => {
+            return index(&state.dir, nonce.as_ref()).await;
+        }
+        StatusCode::OK | StatusCode::NOT_MODIFIED => {}
+        _ => return response.map(Body::new),
+    }
+
+    let cache_control = if is_hashed(&path) {
+        IMMUTABLE
+    } else {
+        SHORT_LIVED
+    };
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+
+    let Some(etag) = etag(headers) else {
+        return response.map(Body::new);
+    };
+    if if_none_match.is_some_and(|value| matches_etag(&value, &etag)) {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        for name in [header::CACHE_CONTROL, header::LAST_MODIFIED, header::VARY] {
+            if let Some(value) = headers.get(&name) {
+                not_modified.headers_mut().insert(name, value.clone());
+            }
+        }
+        not_modified.headers_mut().insert(header::ETAG, etag);
+        return not_modified;
+    }
+    headers.insert(header::ETAG, etag);
+    response.map(Body::new)
+}
+
+/// `index.html` with the CSP nonce filled in. Never cached without
+/// revalidation, so a deploy is picked up on the next load.
+async fn index(dir: &Path, nonce: Option<&CspNonce>) -> Response {
+    let html = match tokio::fs::read_to_string(dir.join("index.html")).await {
+        Ok(html) => html,
+        Err(e) => {
+            tracing::error!(error = %e, dir = %dir.display(), "Failed to read index.html");
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+    let html = match nonce {
+        Some(nonce) => html.replace(NONCE_PLACEHOLDER, nonce.as_str()),
+        None => html,
+    };
+
+    let mut response = Html(html).into_response();
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(NO_CACHE));
+    response
+}
+
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Whether the file name carries a content hash, like Vite's
+/// `assets/index-BfH2x9_k.js`.
+fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or_default();
+    stem.rsplit_once('-').is_some_and(|(_, hash)| {
+        hash.len() >= 8
+            && hash.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && hash
+                .chars()
+                .any(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+    })
+}
+
+/// A weak validator from the served file's size, modification time and
+/// encoding, so the `.br`, `.gz` and plain variants differ.
+fn etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let length = headers.get(header::CONTENT_LENGTH)?.to_str().ok()?;
+    let modified = headers.get(header::LAST_MODIFIED)?.to_str().ok()?;
+    let modified = httpdate::parse_http_date(modified)
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let encoding = headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|encoding| format!("-{}", encoding))
+        .unwrap_or_default();
+
+    HeaderValue::from_str(&format!("W/\"{:x}-{}{}\"", modified, length, encoding)).ok()
+}
+
+/// Weak comparison against an `If-None-Match` list, as required for `GET`.
+fn matches_etag(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(candidates) = if_none_match.to_str() else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag.to_str().unwrap_or_default());
+
+    candidates
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, http::Request};
+
+    struct Dist(PathBuf);
+
+    impl Dist {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("queso-static-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(dir.join("assets")).unwrap();
+            std::fs::write(
+                dir.join("index.html"),
+                r#"<script nonce="__CSP_NONCE__" src="/assets/index-BfH2x9_k.js"></script>"#,
+            )
+            .unwrap();
+            std::fs::write(dir.join("assets/index-BfH2x9_k.js"), "console.log(1)").unwrap();
+            std::fs::write(dir.join("assets/index-BfH2x9_k.js.br"), "brotli").unwrap();
+            std::fs::write(dir.join("favicon.ico"), "icon").unwrap();
+            Self(dir)
+        }
+
+        fn app(&self) -> Router {
+            static_files(&self.0).layer(Extension(CspNonce::for_tests("abc123")))
+        }
+    }
+
+    impl Drop for Dist {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+        let response = app.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8_lossy(&bytes).into_owned(),
+        )
+    }
+
+    fn get(uri: &str) -> axum::http::request::Builder {
+        Request::get(uri)
+    }
+
+    #[test]
+    fn test_is_hashed() {
+        assert!(is_hashed("/assets/index-BfH2x9_k.js"));
+        assert!(is_hashed("/assets/vendor-C8a1b2c3.js.map"));
+        assert!(!is_hashed("/favicon.ico"));
+        assert!(!is_hashed("/assets/some-longword.js"));
+    }
+
+    #[tokio::test]
+    async fn test_index_is_revalidated_and_gets_the_nonce() {
+        let dist = Dist::new("index");
+
+        for uri in ["/", "/index.html", "/users/42"] {
+            let request = get(uri)
+                .header(header::ACCEPT, "text/html,*/*")
+                .body(Body::empty())
+                .unwrap();
+            let (status, headers, body) = send(dist.app(), request).await;
+
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(headers[header::CACHE_CONTROL], NO_CACHE);
+            assert!(body.contains(r#"nonce="abc123""#), "{}", body);
+        }
+
+        // Missing assets are not answered with the page
+        let request = get("/assets/missing-Abcdef12.js")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, _) = send(dist.app(), request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_assets_are_cached_and_precompressed() {
+        let dist = Dist::new("assets");
+
+        let request = get("/assets/index-BfH2x9_k.js")
+            .header(header::ACCEPT_ENCODING, "br, gzip")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(dist.app(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CACHE_CONTROL], IMMUTABLE);
+        assert_eq!(headers[header::CONTENT_ENCODING], "br");
+        assert_eq!(body, "brotli");
+        let compressed_etag = headers[header::ETAG].clone();
+
+        let request = get("/assets/index-BfH2x9_k.js")
+            .body(Body::empty())
+            .unwrap();
+        let (_, headers, body) = send(dist.app(), request).await;
+        assert_eq!(body, "console.log(1)");
+        assert_ne!(headers[header::ETAG], compressed_etag);
+
+        let request = get("/favicon.ico").body(Body::empty()).unwrap();
+        let (_, headers, _) = send(dist.app(), request).await;
+        assert_eq!(headers[header::CACHE_CONTROL], SHORT_LIVED);
+    }
+
+    #[tokio::test]
+    async fn test_matching_etag_is_not_modified() {
+        let dist = Dist::new("etag");
+
+        let request = get("/favicon.ico").body(Body::empty()).unwrap();
+        let (_, headers, _) = send(dist.app(), request).await;
+        let etag = headers[header::ETAG].clone();
+
+        let request = get("/favicon.ico")
+            .header(header::IF_NONE_MATCH, etag.clone())
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(dist.app(), request).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert_eq!(headers[header::ETAG], etag);
+        assert!(body.is_empty());
+
+        let request = get("/favicon.ico")
+            .header(header::IF_NONE_MATCH, "W/\"0-0\"")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, _) = send(dist.app(), request).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{borrow::Cow, fmt};
+
+use serde_json::{Map, Value};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+use tracing_subscriber::{
+    field::MakeExt,
+    fmt::{
+        FmtContext, FormatEvent, FormatFields,
+        format::{self, Writer},
+    },
+    layer::Context,
+    registry::LookupSpan,
+};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Field names (matched case-insensitively as substrings) whose values are
+/// never logged.
+const SENSITIVE_FIELDS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "authorization",
+    "cookie",
+    "api_key",
+    "credential",
+];
+
+/// Span fields lifted to the top level of every JSON log line.
+const CORRELATION_FIELDS: &[&str] = &["request_id", "user_id", "route"];
+
+pub fn is_sensitive(field: &str) -> bool {
+    let field = field.to_ascii_lowercase();
+    SENSITIVE_FIELDS
+        .iter()
+        .any(|sensitive| field.contains(sensitive))
+}
+
+/// Masks `Bearer <token>` credentials embedded in free text.
+pub fn redact_bearer(text: &str) -> Cow<'_, str> {
+    const BEARER: &str = "bearer ";
+    let lower = text.to_ascii_lowercase();
+    if !lower.contains(BEARER) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = 0;
+    for (start, _) in lower.match_indices(BEARER) {
+        if start < rest {
+            continue;
+        }
+        let token_start = start + BEARER.len();
+        let token_end = text[token_start..]
+            .find(|c: char| c.is_whitespace() || c == '"' || c == ',')
+            .map_or(text.len(), |end| token_start + end);
+        redacted.push_str(&text[rest..token_start]);
+        redacted.push_str(REDACTED);
+        rest = token_end;
+    }
+    redacted.push_str(&text[rest..]);
+    Cow::Owned(redacted)
+}
+
+/// Field formatter for the text format that applies the same redaction as
+/// the JSON format.
+pub fn redacting_fields() -> impl for<'writer> FormatFields<'writer> + Send + Sync + 'static {
+    format::debug_fn(
+        |writer: &mut Writer<'_>, field: &Field, value: &dyn fmt::Debug| {
+            if is_sensitive(field.name()) {
+                return write!(writer, "{}={}", field, REDACTED);
+            }
+
+            let value = format!("{:?}", value);
+            if field.name() == "message" {
+                write!(writer, "{}", redact_bearer(&value))
+            } else {
+                write!(writer, "{}={}", field, redact_bearer(&value))
+            }
+        },
+    )
+    .delimited(" ")
+}
+
+/// Collects fields into JSON values, redacting as it goes.
+#[derive(Default)]
+struct JsonFields(Map<String, Value>);
+
+impl JsonFields {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let value = if is_sensitive(field.name()) {
+            Value::from(REDACTED)
+        } else {
+            value
+        };
+        self.0.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(redact_bearer(value)));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(redact_bearer(&format!("{:?}", value))));
+    }
+}
+
+/// Keeps each span's fields as JSON so [`JsonFormat`] can read them.
+pub struct SpanFieldsLayer;
+
+impl<S> tracing_subscriber::Layer<S> for SpanFieldsLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = JsonFields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<JsonFields>()
+        {
+            values.record(fields);
+        }
+    }
+}
+
+/// One JSON object per line with `timestamp`, `level`, `target`, `message`,
+/// the correlation fields (`request_id`, `user_id`, `route`; `null` when not
+/// in a request) and any remaining event fields under `fields`.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        let mut fields = fields.0;
+
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".into(),
+            Value::from(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+        );
+        line.insert("level".into(), Value::from(metadata.level().as_str()));
+        line.insert("target".into(), Value::from(metadata.target()));
+        line.insert(
+            "message".into(),
+            fields.remove("message").unwrap_or(Value::Null),
+        );
+
+        for name in CORRELATION_FIELDS {
+            line.insert(name.to_string(), Value::Null);
+        }
+        if let Some(scope) = ctx.event_scope() {
+            // Innermost span wins
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<JsonFields>() {
+                    for name in CORRELATION_FIELDS {
+                        if let Some(value) = span_fields.0.get(*name) {
+                            line.insert(name.to_string(), value.clone());
+                        }
+                    }
+                }
+            }
+            if let Some(span) = ctx.lookup_current() {
+                line.insert("span".into(), Value::from(span.name()));
+            }
+        }
+
+        if !fields.is_empty() {
+            line.insert("fields".into(), Value::Object(fields));
+        }
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_json_lines_carry_correlation_fields_and_redact() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(SpanFieldsLayer).with(
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .fmt_fields(redacting_fields())
+                .with_writer(buffer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "http_request",
+                request_id = "req-1",
+                route = "/api/users/{id}",
+                user_id = tracing::field::Empty
+            );
+            let _entered = span.enter();
+            span.record("user_id", 42);
+            tracing::info!(
+                password = "hunter2",
+                header = "Bearer abc.def.ghi",
+                attempts = 3,
+                "login with Authorization: Bearer abc.def.ghi"
+            );
+        });
+
+        let output = buffer.contents();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["request_id"], "req-1");
+        assert_eq!(line["user_id"], 42);
+        assert_eq!(line["route"], "/api/users/{id}");
+        assert_eq!(line["fields"]["password"], REDACTED);
+        assert_eq!(line["fields"]["attempts"], 3);
+        assert!(line["timestamp"].is_string());
+        assert!(!output.contains("hunter2"));
+        assert!(!output.contains("abc.def.ghi"));
+    }
+
+    #[test]
+    fn test_text_fields_are_redacted() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(redacting_fields())
+                .with_writer(buffer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(jwt_token = "abc", user = "bob", "signed in");
+        });
+
+        let output = buffer.contents();
+        assert!(output.contains("jwt_token=[REDACTED]"));
+        assert!(output.contains("user=\"bob\""));
+        assert!(!output.contains("abc"));
+    }
+
+    #[test]
+    fn test_redact_bearer() {
+        assert_eq!(
+            redact_bearer("Authorization: Bearer abc.def, next"),
+            "Authorization: Bearer [REDACTED], next"
+        );
+        assert_eq!(redact_bearer("no credentials here"), "no credentials here");
+    }
+}
@@ -0,0 +1,162 @@
+use std::{sync::OnceLock, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use r2d2::event::{CheckoutEvent, HandleEvent, TimeoutEvent};
+
+use crate::config::database::DbPool;
+
+const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+const HTTP_REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+const DB_POOL_CONNECTIONS: &str = "db_pool_connections";
+const DB_POOL_MAX_SIZE: &str = "db_pool_max_size";
+const DB_POOL_WAIT_SECONDS: &str = "db_pool_wait_seconds";
+const DB_POOL_TIMEOUTS_TOTAL: &str = "db_pool_timeouts_total";
+const AUTH_LOGINS_TOTAL: &str = "auth_logins_total";
+const USER_SIGNUPS_TOTAL: &str = "user_signups_total";
+const RATE_LIMITED_TOTAL: &str = "http_rate_limited_total";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the global Prometheus recorder on first use and returns a handle
+/// for rendering it. Metrics recorded before this is called are dropped.
+pub fn recorder() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)
+                .expect("Latency buckets are not empty")
+                .install_recorder()
+                .expect("Failed to install Prometheus recorder")
+        })
+        .clone()
+}
+
+#[derive(Clone)]
+pub struct MetricsState {
+    pub handle: PrometheusHandle,
+    pub pool: DbPool,
+}
+
+/// `GET /metrics` in the Prometheus text format.
+pub async fn render(State(state): State<MetricsState>) -> String {
+    // Pool occupancy is sampled at scrape time
+    let pool_state = state.pool.state();
+    let idle = pool_state.idle_connections;
+    gauge!(DB_POOL_CONNECTIONS, "state" => "idle").set(idle);
+    gauge!(DB_POOL_CONNECTIONS, "state" => "in_use").set(pool_state.connections - idle);
+    gauge!(DB_POOL_MAX_SIZE).set(state.pool.max_size());
+
+    state.handle.render()
+}
+
+/// Counts requests and records their latency, labelled by method, matched
+/// route template and status. Unmatched requests (the static UI fallback)
+/// share one label so paths can't blow up cardinality.
+pub async fn track_http(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "fallback".to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION_SECONDS, &labels).record(started.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Records how long requests wait for a pooled connection.
+#[derive(Debug)]
+pub struct PoolMetrics;
+
+impl HandleEvent for PoolMetrics {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        histogram!(DB_POOL_WAIT_SECONDS).record(event.duration().as_secs_f64());
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        histogram!(DB_POOL_WAIT_SECONDS).record(event.timeout().as_secs_f64());
+        counter!(DB_POOL_TIMEOUTS_TOTAL).increment(1);
+    }
+}
+
+/// Outcome label for `auth_logins_total`.
+#[derive(Debug, Clone, Copy)]
+pub enum LoginOutcome {
+    Success,
+    InvalidCredentials,
+    Error,
+}
+
+impl LoginOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::InvalidCredentials => "invalid_credentials",
+            LoginOutcome::Error => "error",
+        }
+    }
+}
+
+pub fn record_login(method: &'static str, outcome: LoginOutcome) {
+    counter!(AUTH_LOGINS_TOTAL, "method" => method, "outcome" => outcome.as_str()).increment(1);
+}
+
+pub fn record_signup(provider: &'static str) {
+    counter!(USER_SIGNUPS_TOTAL, "provider" => provider).increment(1);
+}
+
+pub fn record_rate_limited(policy: &'static str) {
+    counter!(RATE_LIMITED_TOTAL, "policy" => policy).increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_requests_are_labelled_by_route_template() {
+        let handle = recorder();
+        let app = Router::new()
+            .route("/metrics-test/{id}", get(|| async { "ok" }))
+            .layer(middleware::from_fn(track_http));
+
+        for uri in ["/metrics-test/1", "/metrics-test/2", "/not-a-route"] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let rendered = handle.render();
+        assert!(
+            rendered.contains(
+                r#"http_requests_total{method="GET",route="/metrics-test/{id}",status="200"} 2"#
+            ),
+            "{}",
+            rendered
+        );
+        assert!(rendered.contains(r#"route="fallback",status="404""#));
+        assert!(rendered.contains("http_request_duration_seconds_bucket"));
+        assert!(!rendered.contains("/metrics-test/1"));
+    }
+}
@@ -0,0 +1,72 @@
+pub mod logging;
+pub mod metrics;
+pub mod trace;
+
+use opentelemetry::global;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+use crate::config::settings::{LogFormat, LoggingSettings, TelemetrySettings};
+
+/// Flushes exported spans when dropped; keep it alive for the whole process.
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Installs the global subscriber: level filtering, text or JSON logs with
+/// sensitive fields redacted and, when an OTLP endpoint is configured, trace
+/// export.
+pub fn init(settings: &TelemetrySettings, logging: &LoggingSettings) -> TelemetryGuard {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = trace::otlp_provider(settings);
+
+    let fmt_layer = match logging.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .fmt_fields(logging::redacting_fields())
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .event_format(logging::JsonFormat)
+            .fmt_fields(logging::redacting_fields())
+            .boxed(),
+    };
+
+    let layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = vec![
+        env_filter(logging).boxed(),
+        logging::SpanFieldsLayer.boxed(),
+        fmt_layer,
+        provider.as_ref().map(trace::otel_layer).boxed(),
+    ];
+    tracing_subscriber::registry().with(layers).init();
+
+    if let Some(endpoint) = &settings.otlp_endpoint
+        && provider.is_some()
+    {
+        tracing::info!("Exporting traces to {}", endpoint);
+    }
+
+    TelemetryGuard { provider }
+}
+
+/// `RUST_LOG` when set, otherwise the configured level plus overrides.
+fn env_filter(logging: &LoggingSettings) -> EnvFilter {
+    if let Ok(filter) = std::env::var("RUST_LOG") {
+        return EnvFilter::new(filter);
+    }
+
+    logging.overrides.iter().cloned().fold(
+        EnvFilter::new(logging.level.to_string()),
+        |filter, directive| filter.add_directive(directive),
+    )
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderMap, HeaderName, HeaderValue, Request, Response},
+};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::TracerProvider as _,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{DefaultOnRequest, MakeSpan, OnResponse, TraceLayer},
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{api::RequestId, config::settings::TelemetrySettings};
+
+/// Builds the OTLP/HTTP tracer provider, or `None` when no endpoint is
+/// configured or the exporter can't be created.
+pub fn otlp_provider(settings: &TelemetrySettings) -> Option<SdkTracerProvider> {
+    let endpoint = settings.otlp_endpoint.as_deref()?;
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build();
+
+    match exporter {
+        Ok(exporter) => Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(
+                    Resource::builder()
+                        .with_service_name(settings.service_name.clone())
+                        .build(),
+                )
+                .build(),
+        ),
+        Err(e) => {
+            eprintln!(
+                "Failed to create OTLP exporter, traces will not be exported: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// The `tracing` layer forwarding spans to `provider`.
+pub fn otel_layer<S>(
+    provider: &SdkTracerProvider,
+) -> tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("queso-server"))
+}
+
+pub type HttpTraceLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    RequestSpan,
+    DefaultOnRequest,
+    RecordStatus,
+>;
+
+/// One span per request, continuing any incoming W3C `traceparent`.
+/// Must sit inside the request id middleware so the id can be recorded.
+pub fn http_layer() -> HttpTraceLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(RequestSpan)
+        .on_response(RecordStatus)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestSpan;
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let method = request.method();
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str)
+            .unwrap_or("fallback");
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map(RequestId::as_str)
+            .unwrap_or("-");
+
+        let span = tracing::info_span!(
+            "http_request",
+            otel.name = %format!("{} {}", method, route),
+            otel.kind = "server",
+            %method,
+            route,
+            request_id,
+            user_id = tracing::field::Empty,
+            status = tracing::field::Empty,
+        );
+
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(request.headers()))
+        });
+        span.set_parent(parent);
+        span
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordStatus;
+
+impl<B> OnResponse<B> for RecordStatus {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        span.record("status", response.status().as_u16());
+        tracing::debug!(
+            latency_ms = latency.as_millis() as u64,
+            "finished processing request"
+        );
+    }
+}
+
+/// Headers carrying the current span's trace context, for outgoing calls.
+pub fn propagation_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_incoming_trace_context_is_propagated_to_outgoing_calls() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(otel_layer(&provider));
+
+        let request = Request::get("/api/users")
+            .header("traceparent", TRACEPARENT)
+            .body(())
+            .unwrap();
+
+        let outgoing = tracing::subscriber::with_default(subscriber, || {
+            let span = RequestSpan.make_span(&request);
+            span.in_scope(propagation_headers)
+        });
+
+        let traceparent = outgoing["traceparent"].to_str().unwrap();
+        // Same trace, new parent span
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+    }
+}
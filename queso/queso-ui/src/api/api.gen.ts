@@ -0,0 +1,165 @@
+// Generated by `queso api export` from the server's OpenAPI document.
+// Do not edit; change the server and run the command again.
+
+import type { AxiosInstance } from 'axios';
+
+/** Outcome of a single readiness check. */
+export interface CheckResult {
+  /** Why the check failed; never contains internal details */
+  error?: string | null;
+  /** Time the check took, in milliseconds */
+  latency_ms: number;
+  status: HealthStatus;
+}
+
+export interface CreateUserRequest {
+  email: string;
+  password: string;
+  username: string;
+}
+
+/** The signed-in user, without the account details `User` carries. */
+export interface CurrentUser {
+  email: string;
+  id: number;
+  username: string;
+}
+
+export interface EmailLoginRequest {
+  email: string;
+  password: string;
+}
+
+/** A single failing field and the reason it was rejected. */
+export interface FieldError {
+  field: string;
+  reason: string;
+}
+
+export interface GoogleUser {
+  email: string;
+  family_name: string;
+  given_name: string;
+  id: string;
+  locale: string;
+  name: string;
+  picture: string;
+  verified_email: boolean;
+}
+
+export interface HealthResponse {
+  /** Per-check results, keyed by check name; empty for liveness */
+  checks?: Record<string, CheckResult>;
+  status: HealthStatus;
+}
+
+export type HealthStatus = 'ok' | 'fail';
+
+export interface LoginResponse {
+  /**
+   * Bearer token; omitted when cookie sessions are enabled and the token
+   * is set as an `HttpOnly` cookie instead
+   */
+  token?: string | null;
+}
+
+export interface NewUser {
+  avatar_url?: string | null;
+  email: string;
+  google_id?: string | null;
+  password_hash: string;
+  username: string;
+}
+
+export interface OAuthCallback {
+  code: string;
+  state: string;
+}
+
+export interface OAuthUrlResponse {
+  url: string;
+}
+
+/** RFC 7807 problem details body shared by all error responses. */
+export interface ProblemDetails {
+  /** Stable machine-readable error code */
+  code: string;
+  /** Human-readable explanation specific to this occurrence */
+  detail: string;
+  /** Per-field errors, present for validation failures */
+  errors?: FieldError[];
+  /** Correlates the response with server logs */
+  request_id?: string | null;
+  status: number;
+  /** Short, human-readable summary of the status */
+  title: string;
+  /** URI identifying the problem type, derived from `code` */
+  type: string;
+}
+
+/** Returned instead of the created user when enumeration protection is enabled. */
+export interface SignupAcceptedResponse {
+  message: string;
+}
+
+/** Fields to change; omitted fields keep their value. */
+export interface UpdateUserRequest {
+  email?: string | null;
+  username?: string | null;
+}
+
+export interface User {
+  avatar_url?: string | null;
+  created_at: string;
+  /** Set while the user is deleted but can still be restored */
+  deleted_at?: string | null;
+  email: string;
+  google_id?: string | null;
+  id: number;
+  updated_at: string;
+  username: string;
+  /** Incremented by every update; the user's `ETag` is derived from it */
+  version: number;
+}
+
+/** Every `/api/v1` operation; `http` must use `/api/v1` as its base URL. */
+export const createApi = (http: AxiosInstance) => ({
+  /** List deleted users */
+  getDeletedUsers: async (): Promise<User[]> =>
+    (await http.get<User[]>('/admin/users/deleted')).data,
+  /** Restore a deleted user */
+  restoreUser: async (id: number): Promise<User> =>
+    (await http.post<User>(`/admin/users/${id}/restore`)).data,
+  /** Handle Google OAuth callback */
+  googleCallback: async (body: OAuthCallback): Promise<LoginResponse> =>
+    (await http.post<LoginResponse>('/auth/google/callback', body)).data,
+  /** Initiate Google OAuth login */
+  googleLogin: async (): Promise<OAuthUrlResponse> =>
+    (await http.get<OAuthUrlResponse>('/auth/google/login')).data,
+  /** Login with email and password */
+  login: async (body: EmailLoginRequest): Promise<LoginResponse> =>
+    (await http.post<LoginResponse>('/auth/login/email', body)).data,
+  /** Logout current user */
+  logout: async (): Promise<void> => {
+    await http.post('/auth/logout');
+  },
+  /** Get current user information */
+  me: async (): Promise<CurrentUser> =>
+    (await http.get<CurrentUser>('/auth/me')).data,
+  /** Get all users */
+  getUsers: async (): Promise<User[]> =>
+    (await http.get<User[]>('/users')).data,
+  /** Create a new user */
+  createUser: async (body: CreateUserRequest): Promise<User | SignupAcceptedResponse> =>
+    (await http.post<User | SignupAcceptedResponse>('/users', body)).data,
+  /** Get a user by ID */
+  getUser: async (id: number, ifNoneMatch?: string | null): Promise<User> =>
+    (await http.get<User>(`/users/${id}`, { headers: { 'If-None-Match': ifNoneMatch } })).data,
+  /** Update a user */
+  updateUser: async (id: number, body: UpdateUserRequest, ifMatch: string): Promise<User> =>
+    (await http.patch<User>(`/users/${id}`, body, { headers: { 'If-Match': ifMatch } })).data,
+  /** Delete a user */
+  deleteUser: async (id: number, ifMatch: string): Promise<void> => {
+    await http.delete(`/users/${id}`, { headers: { 'If-Match': ifMatch } });
+  },
+});
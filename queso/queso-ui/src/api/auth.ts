@@ -1,11 +1,15 @@
 import { useMutation, useQuery } from '@tanstack/react-query';
 import {
   auth,
-  type LoginRequest,
-  type SignupRequest,
+  hasSession,
+  type CreateUserRequest,
+  type CurrentUser,
+  type EmailLoginRequest,
+  type LoginResponse,
+  type OAuthCallback,
+  type OAuthUrlResponse,
+  type SignupAcceptedResponse,
   type User,
-  type AuthResponse,
-  type GoogleCodeExchangeRequest,
 } from './client';
 import { AxiosError } from 'axios';
 import { updateAuthState } from '@/main';
@@ -23,7 +27,7 @@ export const authKeys = {
 
 // Hooks
 export const useLogin = () => {
-  return useMutation<AuthResponse, AxiosError, LoginRequest>({
+  return useMutation<LoginResponse, AxiosError, EmailLoginRequest>({
     mutationKey: authKeys.login(),
     mutationFn: auth.login,
     onSuccess: () => {
@@ -33,19 +37,19 @@ export const useLogin = () => {
 };
 
 export const useSignup = () => {
-  return useMutation<User, AxiosError, SignupRequest>({
+  return useMutation<User | SignupAcceptedResponse, AxiosError, CreateUserRequest>({
     mutationKey: authKeys.signup(),
     mutationFn: auth.signup,
   });
 };
 
 export const useUser = () => {
-  return useQuery<User, AxiosError>({
+  return useQuery<CurrentUser, AxiosError>({
     queryKey: authKeys.user(),
     queryFn: auth.getUser,
     retry: false,
-    // Only try to get user if we have a token
-    enabled: !!localStorage.getItem('auth_token'),
+    // Only try to get user if we have a session
+    enabled: hasSession(),
   });
 };
 
@@ -60,14 +64,14 @@ export const useLogout = () => {
 };
 
 export const useGoogleLogin = () => {
-  return useMutation<{ url: string }, AxiosError, void>({
+  return useMutation<OAuthUrlResponse, AxiosError, void>({
     mutationKey: authKeys.googleLogin(),
     mutationFn: auth.googleLogin,
   });
 };
 
 export const useExchangeGoogleCode = () => {
-  return useMutation<AuthResponse, AxiosError, GoogleCodeExchangeRequest>({
+  return useMutation<LoginResponse, AxiosError, OAuthCallback>({
     mutationKey: authKeys.exchangeGoogleCode(),
     mutationFn: auth.exchangeGoogleCode,
     onSuccess: () => {
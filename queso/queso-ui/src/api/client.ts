@@ -1,83 +1,94 @@
 import axios, { AxiosError } from 'axios';
 import { env } from '../config/env';
+import {
+  createApi,
+  type CreateUserRequest,
+  type CurrentUser,
+  type EmailLoginRequest,
+  type LoginResponse,
+  type OAuthCallback,
+  type OAuthUrlResponse,
+  type SignupAcceptedResponse,
+  type User,
+} from './api.gen';
 
-// Types
-export interface LoginRequest {
-  email: string;
-  password: string;
-}
+// Types are generated from the server's OpenAPI document by `queso api export`
+export type * from './api.gen';
 
-export interface SignupRequest {
-  username: string;
-  email: string;
-  password: string;
-}
+const CSRF_COOKIE = 'queso_csrf';
+const SAFE_METHODS = ['get', 'head', 'options'];
 
-export interface AuthResponse {
-  token: string;
-  token_type: string;
-}
+const readCookie = (name: string): string | undefined =>
+  document.cookie
+    .split('; ')
+    .find(cookie => cookie.startsWith(`${name}=`))
+    ?.slice(name.length + 1);
 
-export interface User {
-  id: number;
-  username: string;
-  email: string;
-}
-
-export interface GoogleCodeExchangeRequest {
-  code: string;
-  state: string;
-}
+// Bearer token in localStorage, or an HttpOnly session cookie whose
+// companion CSRF cookie is readable
+export const hasSession = (): boolean =>
+  !!localStorage.getItem('auth_token') || !!readCookie(CSRF_COOKIE);
 
 // Create an axios instance with the base URL from environment
 export const apiClient = axios.create({
   baseURL: env.apiBaseUrl,
+  // Send the session cookie when the server uses cookie sessions
+  withCredentials: true,
   headers: {
     'Content-Type': 'application/json',
   },
 });
 
-// Add token to requests if it exists
+// Add the bearer token, or the CSRF token for cookie sessions
 apiClient.interceptors.request.use(config => {
   const token = localStorage.getItem('auth_token');
   if (token) {
     config.headers.Authorization = `Bearer ${token}`;
   }
+  const csrf = readCookie(CSRF_COOKIE);
+  if (csrf && !SAFE_METHODS.includes((config.method ?? 'get').toLowerCase())) {
+    config.headers['X-CSRF-Token'] = csrf;
+  }
   return config;
 });
 
+// Typed calls for every endpoint
+export const api = createApi(apiClient);
+
+const storeToken = (response: LoginResponse) => {
+  if (response.token) {
+    localStorage.setItem('auth_token', response.token);
+  }
+};
+
 // Auth API functions
 export const auth = {
-  login: async (data: LoginRequest): Promise<AuthResponse> => {
-    const response = await apiClient.post<AuthResponse>('/auth/login/email', data);
-    localStorage.setItem('auth_token', response.data.token);
-    return response.data;
+  login: async (data: EmailLoginRequest): Promise<LoginResponse> => {
+    const response = await api.login(data);
+    storeToken(response);
+    return response;
   },
 
-  signup: async (data: SignupRequest): Promise<User> => {
-    const response = await apiClient.post<User>('/users', data);
-    return response.data;
-  },
+  signup: (data: CreateUserRequest): Promise<User | SignupAcceptedResponse> =>
+    api.createUser(data),
 
   logout: async (): Promise<void> => {
-    localStorage.removeItem('auth_token');
-    await apiClient.post('/auth/logout');
+    try {
+      // Needs the token; cookie sessions are expired by the response
+      await api.logout();
+    } finally {
+      localStorage.removeItem('auth_token');
+    }
   },
 
-  getUser: async (): Promise<User> => {
-    const response = await apiClient.get<User>('/auth/me');
-    return response.data;
-  },
+  getUser: (): Promise<CurrentUser> => api.me(),
 
-  googleLogin: async (): Promise<{ url: string }> => {
-    const response = await apiClient.get<{ url: string }>('/auth/google/login');
-    return response.data;
-  },
+  googleLogin: (): Promise<OAuthUrlResponse> => api.googleLogin(),
 
-  exchangeGoogleCode: async (data: GoogleCodeExchangeRequest): Promise<AuthResponse> => {
-    const response = await apiClient.post<AuthResponse>('/auth/google/callback', data);
-    localStorage.setItem('auth_token', response.data.token);
-    return response.data;
+  exchangeGoogleCode: async (data: OAuthCallback): Promise<LoginResponse> => {
+    const response = await api.googleCallback(data);
+    storeToken(response);
+    return response;
   },
 };
 
@@ -85,9 +96,3 @@ export const auth = {
 export const isAxiosError = (error: unknown): error is AxiosError => {
   return axios.isAxiosError(error);
 };
-
-// Example API function
-export const fetchUsers = async () => {
-  const response = await apiClient.get('/users');
-  return response.data;
-};
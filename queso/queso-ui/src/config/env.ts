@@ -12,7 +12,7 @@ interface EnvConfig {
 }
 
 export const env: EnvConfig = {
-  apiBaseUrl: import.meta.env.VITE_API_BASE_URL || 'http://localhost:3000/api',
+  apiBaseUrl: import.meta.env.VITE_API_BASE_URL || 'http://localhost:3000/api/v1',
   enableAnalytics: import.meta.env.VITE_ENABLE_ANALYTICS === 'true',
   posthogApiKey: import.meta.env.VITE_POSTHOG_API_KEY || '',
   posthogHost: import.meta.env.VITE_POSTHOG_HOST || 'https://us.i.posthog.com',
@@ -16,8 +16,6 @@ export function useAuth() {
     } finally {
       // Clear all queries from the cache
       queryClient.clear();
-      // Remove the token from localStorage
-      localStorage.removeItem('token');
     }
   };
 
@@ -1,8 +1,8 @@
 import { useQuery } from '@tanstack/react-query';
-import { auth, User } from '@/api/client';
+import { auth, type CurrentUser } from '@/api/client';
 
 export function useCurrentUser() {
-  const query = useQuery<User>({
+  const query = useQuery<CurrentUser>({
     queryKey: ['currentUser'],
     queryFn: () => auth.getUser(),
     retry: false,
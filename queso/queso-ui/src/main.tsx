@@ -8,6 +8,7 @@ import { QueryClient, QueryClientProvider } from '@tanstack/react-query';
 import { ReactQueryDevtools } from '@tanstack/react-query-devtools';
 import { initPostHog } from '@/config/posthog';
 import { initSentry } from '@/config/sentry';
+import { hasSession } from '@/api/client';
 
 // Initialize monitoring and analytics
 initSentry(); // Initialize Sentry first for better error tracking
@@ -20,7 +21,7 @@ import { routeTree } from './routeTree.gen';
 const router = createRouter({
   routeTree,
   context: {
-    isAuthenticated: !!localStorage.getItem('auth_token'),
+    isAuthenticated: hasSession(),
   },
 });
 
@@ -28,7 +29,7 @@ const router = createRouter({
 export const updateAuthState = () => {
   router.update({
     context: {
-      isAuthenticated: !!localStorage.getItem('auth_token'),
+      isAuthenticated: hasSession(),
     },
   });
 };
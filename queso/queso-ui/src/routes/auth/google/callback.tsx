@@ -32,8 +32,7 @@ function OAuthCallbackComponent() {
       exchangeCode.mutate(
         { code, state },
         {
-          onSuccess: response => {
-            localStorage.setItem('auth_token', response.token);
+          onSuccess: () => {
             toast.success('Successfully logged in with Google');
             PostHog.capture('user_logged_in', { method: 'google' });
             navigate({ to: '/' });
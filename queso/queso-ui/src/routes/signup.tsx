@@ -1,7 +1,7 @@
 import { Button } from '@/components/ui/button';
 import { createFileRoute, useNavigate } from '@tanstack/react-router';
 import { useSignup, useLogin } from '@/api/auth';
-import { isAxiosError } from '@/api/client';
+import { isAxiosError, type ProblemDetails } from '@/api/client';
 import { PostHog } from '@/config/posthog';
 import { z } from 'zod';
 import { useForm } from 'react-hook-form';
@@ -18,10 +18,6 @@ const signupSchema = z.object({
 
 type SignupFormData = z.infer<typeof signupSchema>;
 
-interface ApiErrorResponse {
-  error: string;
-}
-
 function SignupForm({
   onSubmit,
   isLoading,
@@ -101,8 +97,8 @@ function useSignupFlow() {
       navigate({ to: '/' });
     } catch (error) {
       if (isAxiosError(error) && error.response?.data) {
-        const response = error.response.data as ApiErrorResponse;
-        const errorMessage = response.error || 'An error occurred during signup';
+        const problem = error.response.data as ProblemDetails;
+        const errorMessage = problem.detail || 'An error occurred during signup';
         toast.error(errorMessage);
 
         // We parse error messages for field-specific errors to:
@@ -41,6 +41,10 @@ export default defineConfig(({ mode }) => {
         },
       },
     },
+    // The server swaps the placeholder for a per-request CSP nonce
+    html: {
+      cspNonce: '__CSP_NONCE__',
+    },
     build: {
       sourcemap: true,
       rollupOptions: {